@@ -1,71 +1,358 @@
-use pgrx::pg_extern;
+use pgrx::{
+    datum::{Range, RangeBound, TimestampWithTimeZone},
+    default, ereport,
+    iter::{SetOfIterator, TableIterator},
+    name, pg_aggregate, pg_extern, pg_sys, Aggregate, PgSqlErrorCode,
+};
 
-use chrono::{Datelike, Days, MappedLocalTime, NaiveDate, TimeZone, Utc};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    num::{IntErrorKind, ParseIntError},
+    str::FromStr,
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Datelike, Days, MappedLocalTime, NaiveDate, NaiveTime, Offset, TimeDelta, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
 use icu::{calendar::Date, collections::codepointtrie::TrieValue};
-use icu_calendar::{persian::Persian, Iso};
+use icu_calendar::{islamic::IslamicCivil, julian::Julian, persian::Persian, Gregorian, Iso};
 
 pgrx::pg_module_magic!();
 
-fn jalali_date_parse(date: &str) -> Date<Persian> {
-    let splitted: Vec<&str> = date.split("/").collect();
-    if splitted.len() != 3 {
-        panic!("invalid date {date} format");
+fn invalid_datetime_error(message: String) -> ! {
+    ereport!(
+        ERROR,
+        PgSqlErrorCode::ERRCODE_INVALID_DATETIME_FORMAT,
+        message
+    );
+}
+
+fn invalid_parameter_error(message: String) -> ! {
+    ereport!(
+        ERROR,
+        PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        message
+    );
+}
+
+/// Replaces each Persian or Arabic-Indic digit with its ASCII digit independently, so
+/// strings mixing digit systems are normalized too.
+fn digits_to_ascii(s: &str) -> String {
+    s.chars()
+        .map(|x| match x {
+            '۰'..='۹' => char::from(b'0' + (x as u32 - '۰' as u32) as u8),
+            '٠'..='٩' => char::from(b'0' + (x as u32 - '٠' as u32) as u8),
+            _ => x,
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum DateField {
+    Year,
+    Month,
+    Day,
+}
+
+impl DateField {
+    fn name(self) -> &'static str {
+        match self {
+            DateField::Year => "year",
+            DateField::Month => "month",
+            DateField::Day => "day",
+        }
     }
+}
 
-    let year = match splitted[0].parse::<i32>() {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} year value"),
-    };
-    let month = match splitted[1].parse::<u8>() {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} month value"),
+/// Why date_fields_try_parse rejected a date, before any calendar validation.
+enum DateFieldsError {
+    FieldCount,
+    NonNumeric(DateField),
+    OutOfRange(DateField),
+}
+
+impl DateFieldsError {
+    fn from_parse_int(field: DateField, error: ParseIntError) -> Self {
+        match error.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                DateFieldsError::OutOfRange(field)
+            }
+            _ => DateFieldsError::NonNumeric(field),
+        }
+    }
+
+    fn message(&self, date: &str) -> String {
+        format!("invalid date {date} {}", self.detail())
+    }
+
+    /// What was wrong, as it follows the input in error messages.
+    fn detail(&self) -> String {
+        match self {
+            DateFieldsError::FieldCount => "format".to_string(),
+            DateFieldsError::NonNumeric(x) | DateFieldsError::OutOfRange(x) => {
+                format!("{} value", x.name())
+            }
+        }
+    }
+
+    fn diagnostic(&self) -> String {
+        match self {
+            DateFieldsError::FieldCount => "wrong field count".to_string(),
+            DateFieldsError::NonNumeric(x) => format!("non-numeric {}", x.name()),
+            DateFieldsError::OutOfRange(DateField::Day) => "day out of range for month".to_string(),
+            DateFieldsError::OutOfRange(x) => format!("{} out of range", x.name()),
+        }
+    }
+}
+
+/// Normalizes digits and splits on `/`, or on `-` when there is no `/`. A leading `-` is
+/// the sign of the first field rather than a separator.
+fn date_fields_split(date: &str) -> Vec<String> {
+    let normalized = digits_to_ascii(date.trim());
+    let (sign, unsigned_date) = match normalized.strip_prefix('-') {
+        Some(x) => ("-", x),
+        None => ("", normalized.as_str()),
     };
-    let day = match splitted[2].parse::<u8>() {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} day value"),
+    let separator = if unsigned_date.contains('/') {
+        '/'
+    } else {
+        '-'
     };
+    let mut fields: Vec<String> = unsigned_date.split(separator).map(str::to_string).collect();
+    fields[0].insert_str(0, sign);
+    fields
+}
+
+fn date_field_parse<T: FromStr<Err = ParseIntError>>(
+    value: &str,
+    field: DateField,
+) -> Result<T, DateFieldsError> {
+    value
+        .parse::<T>()
+        .map_err(|e| DateFieldsError::from_parse_int(field, e))
+}
+
+/// Splits a date into year, month and day. Either `/` or `-` may separate the fields,
+/// month and day may be unpadded, and Persian or Arabic-Indic digits are read as ASCII.
+fn date_fields_try_parse(date: &str) -> Result<(i32, u8, u8), DateFieldsError> {
+    match date_fields_split(date).as_slice() {
+        [year, month, day] => Ok((
+            date_field_parse(year, DateField::Year)?,
+            date_field_parse(month, DateField::Month)?,
+            date_field_parse(day, DateField::Day)?,
+        )),
+        _ => Err(DateFieldsError::FieldCount),
+    }
+}
+
+/// Year and month of a `YYYY/MM` value, read with the same rules as date_fields_try_parse.
+fn year_month_fields_try_parse(ym: &str) -> Result<(i32, u8), DateFieldsError> {
+    match date_fields_split(ym).as_slice() {
+        [year, month] => Ok((
+            date_field_parse(year, DateField::Year)?,
+            date_field_parse(month, DateField::Month)?,
+        )),
+        _ => Err(DateFieldsError::FieldCount),
+    }
+}
 
+fn jalali_date_try_parse(date: &str) -> Result<Date<Persian>, String> {
+    let (year, month, day) = date_fields_try_parse(date).map_err(|e| e.message(date))?;
     match Date::try_new_persian_date(year, month, day) {
+        Ok(x) => Ok(x),
+        _ => Err(format!("invalid date {date} jalali date")),
+    }
+}
+
+fn jalali_date_parse(date: &str) -> Date<Persian> {
+    match jalali_date_try_parse(date) {
+        Ok(x) => x,
+        Err(e) => invalid_datetime_error(e),
+    }
+}
+
+fn jalali_is_leap_year(year: i32) -> bool {
+    match Date::try_new_persian_date(year, 1, 1) {
+        Ok(x) => x.is_in_leap_year(),
+        _ => invalid_parameter_error(format!("invalid year {year}")),
+    }
+}
+
+fn jalali_days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1..=6 => 31,
+        7..=11 => 30,
+        12 if jalali_is_leap_year(year) => 30,
+        12 => 29,
+        _ => invalid_parameter_error(format!("invalid month {month}")),
+    }
+}
+
+fn jalali_date_clamped(year: i32, month: u32, day: u32) -> Date<Persian> {
+    let day = day.min(jalali_days_in_month(year, month));
+    match Date::try_new_persian_date(year, month as u8, day as u8) {
         Ok(x) => x,
-        _ => panic!("invalid date {date} jalali date"),
+        _ => invalid_datetime_error(format!("invalid date {year}/{month}/{day} jalali date")),
+    }
+}
+
+const JALALI_WEEKDAY_NAMES: [&str; 7] = [
+    "شنبه",
+    "یکشنبه",
+    "دوشنبه",
+    "سه‌شنبه",
+    "چهارشنبه",
+    "پنجشنبه",
+    "جمعه",
+];
+
+const ENGLISH_WEEKDAY_NAMES: [&str; 7] = [
+    "Saturday",
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+];
+
+const ARABIC_WEEKDAY_NAMES: [&str; 7] = [
+    "السبت",
+    "الأحد",
+    "الاثنين",
+    "الثلاثاء",
+    "الأربعاء",
+    "الخميس",
+    "الجمعة",
+];
+
+const JALALI_MONTH_NAMES: [&str; 12] = [
+    "فروردین",
+    "اردیبهشت",
+    "خرداد",
+    "تیر",
+    "مرداد",
+    "شهریور",
+    "مهر",
+    "آبان",
+    "آذر",
+    "دی",
+    "بهمن",
+    "اسفند",
+];
+
+const ENGLISH_MONTH_NAMES: [&str; 12] = [
+    "Farvardin",
+    "Ordibehesht",
+    "Khordad",
+    "Tir",
+    "Mordad",
+    "Shahrivar",
+    "Mehr",
+    "Aban",
+    "Azar",
+    "Dey",
+    "Bahman",
+    "Esfand",
+];
+
+const ARABIC_MONTH_NAMES: [&str; 12] = [
+    "فروردين",
+    "أرديبهشت",
+    "خرداد",
+    "تير",
+    "مرداد",
+    "شهريور",
+    "مهر",
+    "آبان",
+    "آذر",
+    "دي",
+    "بهمن",
+    "إسفند",
+];
+
+const DARI_MONTH_NAMES: [&str; 12] = [
+    "حمل",
+    "ثور",
+    "جوزا",
+    "سرطان",
+    "اسد",
+    "سنبله",
+    "میزان",
+    "عقرب",
+    "قوس",
+    "جدی",
+    "دلو",
+    "حوت",
+];
+
+/// Day of week with Saturday = 0 through Friday = 6.
+fn jalali_weekday_index(date: &Date<Persian>) -> usize {
+    (date.day_of_week() as usize % 7 + 1) % 7
+}
+
+fn jalali_weekday_parse(weekday: &str) -> usize {
+    let normalized: String = weekday
+        .chars()
+        .filter(|x| !x.is_whitespace() && *x != '\u{200c}')
+        .collect::<String>()
+        .to_lowercase();
+    let position = JALALI_WEEKDAY_NAMES
+        .iter()
+        .position(|x| x.replace('\u{200c}', "") == normalized)
+        .or_else(|| {
+            ENGLISH_WEEKDAY_NAMES
+                .iter()
+                .position(|x| x.to_lowercase() == normalized)
+        });
+    match position {
+        Some(x) => x,
+        None => invalid_parameter_error(format!("invalid weekday {weekday}")),
+    }
+}
+
+fn format_year(year: i32) -> String {
+    if year < 0 {
+        format!("-{:0>4}", year.unsigned_abs())
+    } else {
+        format!("{:0>4}", year)
     }
 }
 
+fn jalali_date_to_string(date: &Date<Persian>) -> String {
+    format!(
+        "{}/{:0>2}/{:0>2}",
+        format_year(date.year().number),
+        date.month().ordinal,
+        date.day_of_month().0
+    )
+}
+
 fn jalali_date_to_gregorian_internal(date: &str) -> Date<Iso> {
     jalali_date_parse(date).to_iso()
 }
 
 #[pg_extern]
 fn jalali_date_diff_with_addition(date_start: &str, date_end: &str, addition: i32) -> i32 {
-    let iso_date_start = jalali_date_to_gregorian_internal(date_start);
-    let iso_date_end = jalali_date_to_gregorian_internal(date_end);
-
-    let utc_date_start = match Utc.with_ymd_and_hms(
-        iso_date_start.year().number,
-        iso_date_start.month().ordinal,
-        iso_date_start.day_of_month().0,
-        0,
-        0,
-        0,
-    ) {
-        MappedLocalTime::Single(x) => x,
-        _ => panic!("invalid date {date_start} start"),
-    };
-    let utc_date_end = match Utc.with_ymd_and_hms(
-        iso_date_end.year().number,
-        iso_date_end.month().ordinal,
-        iso_date_end.day_of_month().0,
-        0,
-        0,
-        0,
-    ) {
-        MappedLocalTime::Single(x) => x,
-        _ => panic!("invalid date {date_end} end"),
-    };
-
-    let date_interval = date_component::date_component::calculate(&utc_date_start, &utc_date_end);
+    let days = jalali_day_number(&jalali_date_parse(date_end))
+        - jalali_day_number(&jalali_date_parse(date_start));
+    let sign = if days < 0 { -1 } else { 1 };
 
-    (date_interval.interval_days as i32 + addition) * if date_interval.invert { -1 } else { 1 }
+    match i32::try_from(days.abs())
+        .ok()
+        .and_then(|x| x.checked_add(addition))
+        .and_then(|x| x.checked_mul(sign))
+    {
+        Some(x) => x,
+        None => invalid_datetime_error(format!(
+            "invalid date {date_start} {date_end} result overflow"
+        )),
+    }
 }
 
 #[pg_extern]
@@ -75,17 +362,61 @@ fn jalali_date_diff(date_start: &str, date_end: &str) -> i32 {
 
 #[pg_extern]
 fn jalali_date_to_gregorian(date: &str) -> String {
-    let iso_date = jalali_date_to_gregorian_internal(date);
+    iso_date_to_string(&jalali_date_to_gregorian_internal(date))
+}
+
+fn iso_date_to_string(iso_date: &Date<Iso>) -> String {
     format!(
-        "{:0>4}-{:0>2}-{:0>2}",
-        iso_date.year().number,
+        "{}-{:0>2}-{:0>2}",
+        format_year(iso_date.year().number),
         iso_date.month().ordinal,
         iso_date.day_of_month().0,
     )
 }
 
-fn jalali_date_add_days_internal(date: &str, days: i32) -> Date<Persian> {
-    let iso_date = jalali_date_to_gregorian_internal(date);
+fn jalali_to_naive_date(date: &Date<Persian>) -> NaiveDate {
+    let iso_date = date.to_iso();
+    match NaiveDate::from_ymd_opt(
+        iso_date.year().number,
+        iso_date.month().ordinal,
+        iso_date.day_of_month().0,
+    ) {
+        Some(x) => x,
+        None => invalid_datetime_error(format!(
+            "invalid date {} iso conversion",
+            jalali_date_to_string(date)
+        )),
+    }
+}
+
+fn jalali_date_to_naive(date: &str) -> NaiveDate {
+    jalali_to_naive_date(&jalali_date_parse(date))
+}
+
+fn naive_date_to_jalali(date: NaiveDate) -> Date<Persian> {
+    match Date::try_new_iso_date(date.year(), date.month() as u8, date.day() as u8) {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid date {date} new jalali date")),
+    }
+    .to_calendar(Persian)
+}
+
+fn jalali_date_range(date_start: &str, date_end: &str) -> impl Iterator<Item = Date<Persian>> {
+    let start = jalali_date_to_naive(date_start);
+    let end = jalali_date_to_naive(date_end);
+    let (first, last) = if start <= end {
+        (start, end)
+    } else {
+        (end, start)
+    };
+    first
+        .iter_days()
+        .take_while(move |x| *x <= last)
+        .map(naive_date_to_jalali)
+}
+
+fn jalali_date_try_add_days(date: &str, days: i32) -> Result<Date<Persian>, String> {
+    let iso_date = jalali_date_try_parse(date)?.to_iso();
 
     let new_iso_date = match NaiveDate::from_ymd_opt(
         iso_date.year().number,
@@ -93,38 +424,68 @@ fn jalali_date_add_days_internal(date: &str, days: i32) -> Date<Persian> {
         iso_date.day_of_month().0,
     ) {
         Some(x) => x,
-        None => panic!("invalid date {date} iso conversion"),
+        None => return Err(format!("invalid date {date} iso conversion")),
     };
 
     let added_date = match if days > 0 {
         new_iso_date.checked_add_days(Days::new(days as u64))
     } else {
-        new_iso_date.checked_sub_days(Days::new(days.abs() as u64))
+        new_iso_date.checked_sub_days(Days::new(days.unsigned_abs() as u64))
     } {
         Some(x) => x,
-        None => panic!("invalid date {date} add day"),
+        None => return Err(format!("invalid date {date} add day")),
     };
 
     match Date::try_new_iso_date(
-        added_date.year().try_into().unwrap(),
-        (added_date.month0() + 1).try_into().unwrap(),
-        (added_date.day0() + 1).try_into().unwrap(),
+        added_date.year(),
+        added_date.month() as u8,
+        added_date.day() as u8,
     ) {
+        Ok(x) => Ok(x.to_calendar(Persian)),
+        _ => Err(format!("invalid date {date} new jalali date")),
+    }
+}
+
+fn jalali_date_add_days_internal(date: &str, days: i32) -> Date<Persian> {
+    match jalali_date_try_add_days(date, days) {
         Ok(x) => x,
-        _ => panic!("invalid date {date} new jalali date"),
+        Err(e) => invalid_datetime_error(e),
     }
-    .to_calendar(Persian)
 }
 
 #[pg_extern]
 fn jalali_date_add_days(date: &str, days: i32) -> String {
-    let new_jalali_date = jalali_date_add_days_internal(date, days);
-    format!(
-        "{:0>4}/{:0>2}/{:0>2}",
-        new_jalali_date.year().number,
-        new_jalali_date.month().ordinal,
-        new_jalali_date.day_of_month().0
-    )
+    jalali_date_to_string(&jalali_date_add_days_internal(date, days))
+}
+
+fn jalali_date_add_months_internal(date: &str, months: i32, clamp: bool) -> Date<Persian> {
+    let date_value = jalali_date_parse(date);
+    let total = date_value.year().number as i64 * 12
+        + (date_value.month().ordinal as i64 - 1)
+        + months as i64;
+    let year = match i32::try_from(total.div_euclid(12)) {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid date {date} add month")),
+    };
+    let month = total.rem_euclid(12) as u32 + 1;
+    let day = date_value.day_of_month().0;
+
+    if !clamp && day > jalali_days_in_month(year, month) {
+        invalid_datetime_error(format!(
+            "invalid date {date} day {day} does not exist in {year}/{month:0>2}"
+        ));
+    }
+    jalali_date_clamped(year, month, day)
+}
+
+#[pg_extern]
+fn jalali_add_months_checked(date: &str, months: i32, on_invalid: &str) -> String {
+    let clamp = match on_invalid {
+        "clamp" => true,
+        "error" => false,
+        _ => invalid_parameter_error(format!("invalid on_invalid value {on_invalid}")),
+    };
+    jalali_date_to_string(&jalali_date_add_months_internal(date, months, clamp))
 }
 
 #[pg_extern]
@@ -132,50 +493,26 @@ fn jalali_date_now() -> String {
     let now = chrono::offset::Utc::now();
     let new_date = match Date::try_new_iso_date(now.year(), now.month() as u8, now.day() as u8) {
         Ok(x) => x,
-        _ => panic!("invalid date"),
+        _ => invalid_datetime_error("invalid date".to_string()),
     }
     .to_calendar(Persian);
-    format!(
-        "{:0>4}/{:0>2}/{:0>2}",
-        new_date.year().number,
-        new_date.month().ordinal,
-        new_date.day_of_month().0
-    )
+    jalali_date_to_string(&new_date)
+}
+
+fn gregorian_date_try_parse(date: &str) -> Result<Date<Gregorian>, String> {
+    let (year, month, day) = date_fields_try_parse(date).map_err(|e| e.message(date))?;
+    match Date::try_new_gregorian_date(year, month, day) {
+        Ok(x) => Ok(x),
+        _ => Err(format!("invalid date {date} gregorian date")),
+    }
 }
 
 #[pg_extern]
 fn gregorian_date_to_jalali(date: &str) -> String {
-    let splitted: Vec<&str> = date.split("-").collect();
-    if splitted.len() != 3 {
-        panic!("invalid date {date} format");
+    match gregorian_date_try_parse(date) {
+        Ok(x) => jalali_date_to_string(&x.to_calendar(Persian)),
+        Err(e) => invalid_datetime_error(e),
     }
-
-    let year = match splitted[0].parse::<i32>() {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} year value"),
-    };
-    let month = match splitted[1].parse::<u8>() {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} month value"),
-    };
-    let day = match splitted[2].parse::<u8>() {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} day value"),
-    };
-
-    let gregorian_date = match icu::calendar::Date::try_new_gregorian_date(year, month, day) {
-        Ok(x) => x,
-        _ => panic!("invalid date {date} gregorian date"),
-    };
-
-    let jalali_date = gregorian_date.to_calendar(Persian);
-
-    format!(
-        "{:0>4}/{:0>2}/{:0>2}",
-        jalali_date.year().number,
-        jalali_date.month().ordinal,
-        jalali_date.day_of_month().0
-    )
 }
 
 #[pg_extern]
@@ -209,7 +546,7 @@ fn jalali_date_period_state(date: &str, start: i32) -> String {
         }
     }
 
-    if start >= 1 && start <= 31 {
+    if (1..=31).contains(&start) {
         if date_value.day_of_month().0 == start.to_u32() {
             return "End".to_string();
         } else if date_value.day_of_month().0 == start.to_u32() + 1 {
@@ -222,14 +559,3572 @@ fn jalali_date_period_state(date: &str, start: i32) -> String {
     "Unknown".to_string()
 }
 
-#[cfg(any(test, feature = "pg_test"))]
-#[pgrx::pg_schema]
-mod tests {
-    use pgrx::prelude::*;
+const JALALI_LOCALES: [&str; 3] = ["fa", "fa_IR", "fa_AF"];
 
-    #[pg_test]
-    fn test_jalali_date_add_days() {
-        assert_eq!("1403/05/30", crate::jalali_date_add_days("1403/05/28", 2));
+#[pg_extern]
+fn jalali_parse_tagged(s: &str) -> String {
+    let date = match s.split_once(':') {
+        Some((locale, date)) => {
+            if !JALALI_LOCALES.contains(&locale.trim()) {
+                invalid_datetime_error(format!("invalid date {s} locale {locale}"));
+            }
+            date
+        }
+        None => s,
+    };
+    jalali_date_to_string(&jalali_date_parse(date.trim()))
+}
+
+#[pg_extern]
+fn jalali_month_progress(date: &str) -> f64 {
+    let date_value = jalali_date_parse(date);
+    let days_in_month = jalali_days_in_month(date_value.year().number, date_value.month().ordinal);
+    date_value.day_of_month().0 as f64 / days_in_month as f64
+}
+
+#[pg_extern]
+fn jalali_clamp_to_year(date: &str, year: i32) -> String {
+    let date_value = jalali_date_parse(date);
+    jalali_date_to_string(&jalali_date_clamped(
+        year,
+        date_value.month().ordinal,
+        date_value.day_of_month().0,
+    ))
+}
+
+#[pg_extern]
+fn jalali_next_recurrence(anchor: &str, today: &str) -> String {
+    let anchor_value = jalali_date_parse(anchor);
+    let today_value = jalali_date_parse(today);
+    let month = anchor_value.month().ordinal;
+    let day = anchor_value.day_of_month().0;
+
+    let mut next = jalali_date_clamped(today_value.year().number, month, day);
+    if next < today_value {
+        next = jalali_date_clamped(today_value.year().number + 1, month, day);
+    }
+    jalali_date_to_string(&next)
+}
+
+#[pg_extern]
+fn jalali_to_json(date: &str) -> pgrx::JsonB {
+    let date_value = jalali_date_parse(date);
+    pgrx::JsonB(serde_json::json!({
+        "jalali": jalali_date_to_string(&date_value),
+        "gregorian": jalali_date_to_gregorian(date),
+        "weekday": JALALI_WEEKDAY_NAMES[jalali_weekday_index(&date_value)],
+        "is_leap": date_value.is_in_leap_year(),
+    }))
+}
+
+#[pg_extern]
+fn jalali_info(
+    date: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(year, i32),
+        name!(month, i32),
+        name!(day, i32),
+        name!(weekday, String),
+        name!(doy, i32),
+        name!(is_leap, bool),
+        name!(gregorian, String),
+    ),
+> {
+    let date_value = jalali_date_parse(date);
+    TableIterator::once((
+        date_value.year().number,
+        date_value.month().ordinal as i32,
+        date_value.day_of_month().0 as i32,
+        JALALI_WEEKDAY_NAMES[jalali_weekday_index(&date_value)].to_string(),
+        date_value.day_of_year_info().day_of_year as i32,
+        date_value.is_in_leap_year(),
+        jalali_date_to_gregorian(date),
+    ))
+}
+
+/// Milliseconds since the Unix epoch at UTC midnight of the date's Gregorian equivalent.
+#[pg_extern]
+fn jalali_to_epoch_millis(date: &str) -> i64 {
+    jalali_date_to_naive(date)
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .timestamp_millis()
+}
+
+/// Jalali date of the UTC calendar day containing the given epoch milliseconds.
+#[pg_extern]
+fn epoch_millis_to_jalali(ms: i64) -> String {
+    let date_time = match DateTime::from_timestamp_millis(ms) {
+        Some(x) => x,
+        None => invalid_parameter_error(format!("invalid epoch millis {ms}")),
+    };
+    jalali_date_to_string(&naive_date_to_jalali(date_time.date_naive()))
+}
+
+/// Week of month with Saturday-start weeks, where week 1 is the first week holding at least
+/// four days of the month. Leading days before week 1 are reported as week 0.
+#[pg_extern]
+fn jalali_week_of_month_iso(date: &str) -> i32 {
+    let date_value = jalali_date_parse(date);
+    let first_weekday = jalali_weekday_index(&jalali_date_clamped(
+        date_value.year().number,
+        date_value.month().ordinal,
+        1,
+    )) as i32;
+    let week = (date_value.day_of_month().0 as i32 - 1 + first_weekday) / 7;
+    if first_weekday <= 3 {
+        week + 1
+    } else {
+        week
+    }
+}
+
+/// Same weekday in the previous year: the date is shifted back 364 days (52 weeks), or 371
+/// days when the 364-day shift of the last days of Esfand would stay in the same year.
+#[pg_extern]
+fn jalali_prev_year_aligned(date: &str) -> String {
+    let date_value = jalali_date_parse(date);
+    let mut aligned = jalali_date_add_days_internal(date, -364);
+    if aligned.year().number == date_value.year().number {
+        aligned = jalali_date_add_days_internal(date, -371);
+    }
+    jalali_date_to_string(&aligned)
+}
+
+#[pg_extern]
+fn jalali_month_to_gregorian_range(ym: &str) -> String {
+    let (year, month) = match year_month_fields_try_parse(ym) {
+        Ok((year, month)) if (1..=12).contains(&month) => (year, month as u32),
+        Ok(_) => invalid_datetime_error(format!("invalid month {ym} month value")),
+        Err(e) => invalid_datetime_error(format!("invalid month {ym} {}", e.detail())),
+    };
+
+    format!(
+        "{}..{}",
+        iso_date_to_string(&jalali_date_clamped(year, month, 1).to_iso()),
+        iso_date_to_string(&jalali_date_clamped(year, month, 31).to_iso())
+    )
+}
+
+#[pg_extern]
+fn jalali_weekend_only_span(date_start: &str, date_end: &str, friday_only: bool) -> i32 {
+    jalali_date_range(date_start, date_end)
+        .filter(|x| match jalali_weekday_index(x) {
+            6 => true,
+            5 => !friday_only,
+            _ => false,
+        })
+        .count() as i32
+}
+
+#[pg_extern]
+fn jalali_date_trunc_week(date: &str) -> String {
+    let weekday = jalali_weekday_index(&jalali_date_parse(date)) as i32;
+    jalali_date_to_string(&jalali_date_add_days_internal(date, -weekday))
+}
+
+const JALALI_FORMAT_TOKENS: [(&str, char); 5] = [
+    ("YYYY", 'Y'),
+    ("MMMM", 'B'),
+    ("dddd", 'A'),
+    ("MM", 'm'),
+    ("DD", 'd'),
+];
+
+fn jalali_date_format_internal(date: &Date<Persian>, pattern: &str) -> String {
+    let mut result = String::new();
+    let mut chars = pattern.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            if chars.peek() == Some(&'\'') {
+                chars.next();
+                result.push('\'');
+            } else {
+                quoted = !quoted;
+            }
+            continue;
+        }
+
+        let ahead: String = std::iter::once(c).chain(chars.clone().take(3)).collect();
+        let specifier = match JALALI_FORMAT_TOKENS
+            .iter()
+            .find(|(x, _)| !quoted && ahead.starts_with(x))
+        {
+            Some((x, specifier)) => {
+                chars.nth(x.len() - 2);
+                Some(*specifier)
+            }
+            None if quoted || c != '%' => {
+                result.push(c);
+                continue;
+            }
+            None => chars.next(),
+        };
+
+        match specifier {
+            Some('Y') => result.push_str(&format_year(date.year().number)),
+            Some('m') => result.push_str(&format!("{:0>2}", date.month().ordinal)),
+            Some('d') => result.push_str(&format!("{:0>2}", date.day_of_month().0)),
+            Some('j') => result.push_str(&format!("{:0>3}", date.day_of_year_info().day_of_year)),
+            Some('B') => result.push_str(JALALI_MONTH_NAMES[date.month().ordinal as usize - 1]),
+            Some('A') => result.push_str(JALALI_WEEKDAY_NAMES[jalali_weekday_index(date)]),
+            Some('%') => result.push('%'),
+            Some(x) => invalid_parameter_error(format!("invalid pattern {pattern} specifier %{x}")),
+            None => invalid_parameter_error(format!("invalid pattern {pattern} trailing %")),
+        }
+    }
+
+    if quoted {
+        invalid_parameter_error(format!("invalid pattern {pattern} unterminated quote"));
+    }
+    result
+}
+
+#[pg_extern]
+fn jalali_date_format(date: &str, pattern: &str) -> String {
+    jalali_date_format_internal(&jalali_date_parse(date), pattern)
+}
+
+/// Treats `YYYY/MM/DD` with a year in 1300..=1500 as Jalali and `YYYY-MM-DD` with a year in
+/// 1900..=2100 as Gregorian; any other combination is rejected as ambiguous.
+#[pg_extern]
+fn any_date_to_jalali(s: &str) -> String {
+    let s = s.trim();
+    let year = s
+        .split(['/', '-'])
+        .next()
+        .and_then(|x| x.parse::<i32>().ok());
+    match (s.contains('/'), s.contains('-'), year) {
+        (true, false, Some(1300..=1500)) => jalali_date_to_string(&jalali_date_parse(s)),
+        (false, true, Some(1900..=2100)) => gregorian_date_to_jalali(s),
+        _ => invalid_datetime_error(format!("invalid date {s} ambiguous calendar")),
+    }
+}
+
+#[pg_extern]
+fn jalali_days_to_anniversary(date: &str, from: &str) -> i32 {
+    jalali_date_diff(from, &jalali_next_recurrence(date, from))
+}
+
+fn jalali_datetime_try_parse(datetime: &str) -> Result<(Date<Persian>, NaiveTime), String> {
+    let normalized = datetime.trim().replacen('T', " ", 1);
+    let (date, time) = match normalized.split_once(' ') {
+        Some(x) => x,
+        None => return Err(format!("invalid datetime {datetime} format")),
+    };
+    let time = match NaiveTime::parse_from_str(time.trim(), "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time.trim(), "%H:%M"))
+    {
+        Ok(x) => x,
+        _ => return Err(format!("invalid datetime {datetime} time value")),
+    };
+    Ok((jalali_date_try_parse(date)?, time))
+}
+
+fn jalali_datetime_parse(datetime: &str) -> (Date<Persian>, NaiveTime) {
+    match jalali_datetime_try_parse(datetime) {
+        Ok(x) => x,
+        Err(e) => invalid_datetime_error(e),
+    }
+}
+
+fn validate_business_hours(open_hour: i32, close_hour: i32) {
+    if !(0..=24).contains(&open_hour) || !(0..=24).contains(&close_hour) || open_hour >= close_hour
+    {
+        invalid_parameter_error(format!("invalid business hours {open_hour} {close_hour}"));
+    }
+}
+
+#[pg_extern]
+fn jalali_datetime_in_business_hours(datetime: &str, open_hour: i32, close_hour: i32) -> bool {
+    validate_business_hours(open_hour, close_hour);
+    let (date, time) = jalali_datetime_parse(datetime);
+    let seconds = time.num_seconds_from_midnight() as i32;
+    jalali_weekday_index(&date) != 6 && seconds >= open_hour * 3600 && seconds < close_hour * 3600
+}
+
+fn jalali_holidays_parse(holidays: &[String]) -> Vec<Date<Persian>> {
+    holidays.iter().map(|x| jalali_date_parse(x)).collect()
+}
+
+fn jalali_is_working_day(date: &Date<Persian>, holidays: &[Date<Persian>]) -> bool {
+    jalali_weekday_index(date) != 6 && !holidays.contains(date)
+}
+
+#[pg_extern]
+fn jalali_working_minutes(
+    start_dt: &str,
+    end_dt: &str,
+    open_hour: i32,
+    close_hour: i32,
+    holidays: Vec<String>,
+) -> i64 {
+    validate_business_hours(open_hour, close_hour);
+    let holidays = jalali_holidays_parse(&holidays);
+    let (start_date, start_time) = jalali_datetime_parse(start_dt);
+    let (end_date, end_time) = jalali_datetime_parse(end_dt);
+    let start = jalali_to_naive_date(&start_date).and_time(start_time);
+    let end = jalali_to_naive_date(&end_date).and_time(end_time);
+    let (first, last, sign) = if start <= end {
+        (start, end, 1)
+    } else {
+        (end, start, -1)
+    };
+
+    let mut seconds = 0;
+    for day in first.date().iter_days().take_while(|x| *x <= last.date()) {
+        if !jalali_is_working_day(&naive_date_to_jalali(day), &holidays) {
+            continue;
+        }
+        let open = day.and_time(NaiveTime::MIN) + TimeDelta::hours(open_hour as i64);
+        let close = day.and_time(NaiveTime::MIN) + TimeDelta::hours(close_hour as i64);
+        let overlap = (close.min(last) - open.max(first)).num_seconds();
+        if overlap > 0 {
+            seconds += overlap;
+        }
+    }
+    sign * seconds / 60
+}
+
+pub struct JalaliToGregorianAgg;
+
+#[pg_aggregate]
+impl Aggregate for JalaliToGregorianAgg {
+    const NAME: &'static str = "jalali_to_gregorian_agg";
+    const INITIAL_CONDITION: Option<&'static str> = Some("{}");
+    type State = Vec<String>;
+    type Args = name!(date, String);
+
+    fn state(
+        mut current: Self::State,
+        date: Self::Args,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::State {
+        if let Ok(x) = jalali_date_try_parse(&date) {
+            current.push(iso_date_to_string(&x.to_iso()));
+        }
+        current
+    }
+}
+
+#[pg_extern]
+fn jalali_month_bucket(date: &str) -> i32 {
+    let date_value = jalali_date_parse(date);
+    date_value.year().number * 12 + date_value.month().ordinal as i32
+}
+
+#[pg_extern]
+fn jalali_month_bucket_to_label(bucket: i32) -> String {
+    format!(
+        "{}/{:0>2}",
+        format_year((bucket - 1).div_euclid(12)),
+        (bucket - 1).rem_euclid(12) + 1
+    )
+}
+
+/// Number of Saturday-start weeks in the year, using the same four-day rule as
+/// jalali_week_of_month_iso: a week belongs to the year that holds its Tuesday, so the count
+/// is the number of Tuesdays in the year.
+#[pg_extern]
+fn jalali_weeks_in_year(year: i32) -> i32 {
+    let first_weekday = jalali_weekday_index(&jalali_date_clamped(year, 1, 1)) as i32;
+    let days = if jalali_is_leap_year(year) { 366 } else { 365 };
+    let first_tuesday = (3 - first_weekday).rem_euclid(7);
+    (days - 1 - first_tuesday) / 7 + 1
+}
+
+#[pg_extern]
+fn jalali_start_of_day(date: &str) -> String {
+    format!(
+        "{} 00:00:00",
+        jalali_date_to_string(&jalali_date_parse(date))
+    )
+}
+
+#[pg_extern]
+fn jalali_end_of_day(date: &str) -> String {
+    format!(
+        "{} 23:59:59",
+        jalali_date_to_string(&jalali_date_parse(date))
+    )
+}
+
+/// Rata Die of the ISO equivalent, so 0001-01-01 is day 1 as in chrono's
+/// `num_days_from_ce`, computed arithmetically to cover every ICU year.
+fn jalali_day_number(date: &Date<Persian>) -> i64 {
+    let iso_date = date.to_iso();
+    let month = iso_date.month().ordinal as i64;
+    let year = iso_date.year().number as i64 - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + iso_date.day_of_month().0 as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 305
+}
+
+/// Inverse of `jalali_day_number`, going back through ISO rather than chrono.
+fn jalali_date_from_day_number(day_number: i64) -> Option<Date<Persian>> {
+    let days = day_number + 305;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    Date::try_new_iso_date(i32::try_from(year).ok()?, month as u8, day as u8)
+        .ok()
+        .map(|x| x.to_calendar(Persian))
+}
+
+#[pg_extern]
+fn jalali_closest(target: &str, candidates: Vec<String>) -> Option<String> {
+    let target = jalali_day_number(&jalali_date_parse(target));
+    candidates
+        .iter()
+        .filter_map(|x| jalali_date_try_parse(x).ok())
+        .min_by_key(|x| ((jalali_day_number(x) - target).abs(), *x))
+        .map(|x| jalali_date_to_string(&x))
+}
+
+#[pg_extern]
+fn jalali_farthest(target: &str, candidates: Vec<String>) -> Option<String> {
+    let target = jalali_day_number(&jalali_date_parse(target));
+    candidates
+        .iter()
+        .filter_map(|x| jalali_date_try_parse(x).ok())
+        .max_by_key(|x| ((jalali_day_number(x) - target).abs(), Reverse(*x)))
+        .map(|x| jalali_date_to_string(&x))
+}
+
+pub struct JalaliMode;
+
+#[pg_aggregate]
+impl Aggregate for JalaliMode {
+    const NAME: &'static str = "jalali_mode";
+    const INITIAL_CONDITION: Option<&'static str> = Some("{}");
+    type State = Vec<i64>;
+    type Args = name!(date, String);
+    type Finalize = Option<String>;
+
+    fn state(
+        mut current: Self::State,
+        date: Self::Args,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::State {
+        if let Ok(x) = jalali_date_try_parse(&date) {
+            current.push(jalali_day_number(&x));
+        }
+        current
+    }
+
+    fn finalize(
+        current: Self::State,
+        _direct_args: Self::OrderedSetArgs,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::Finalize {
+        let mut counts = BTreeMap::new();
+        for x in current {
+            *counts.entry(x).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(x, count)| (*count, Reverse(*x)))
+            .and_then(|(x, _)| jalali_date_from_day_number(x))
+            .map(|x| jalali_date_to_string(&x))
+    }
+}
+
+pub struct JalaliPercentile;
+
+#[pg_aggregate]
+impl Aggregate for JalaliPercentile {
+    const NAME: &'static str = "jalali_percentile";
+    const ORDERED_SET: bool = true;
+    const INITIAL_CONDITION: Option<&'static str> = Some("{}");
+    type State = Vec<i64>;
+    type Args = name!(date, String);
+    type OrderedSetArgs = name!(fraction, f64);
+    type Finalize = Option<String>;
+
+    fn state(
+        mut current: Self::State,
+        date: Self::Args,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::State {
+        if let Ok(x) = jalali_date_try_parse(&date) {
+            current.push(jalali_day_number(&x));
+        }
+        current
+    }
+
+    fn finalize(
+        mut current: Self::State,
+        fraction: Self::OrderedSetArgs,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::Finalize {
+        if !(0.0..=1.0).contains(&fraction) {
+            invalid_parameter_error(format!("invalid fraction {fraction}"));
+        }
+        if current.is_empty() {
+            return None;
+        }
+        current.sort_unstable();
+        let rank = ((fraction * current.len() as f64).ceil() as usize).clamp(1, current.len());
+        jalali_date_from_day_number(current[rank - 1]).map(|x| jalali_date_to_string(&x))
+    }
+}
+
+#[pg_extern]
+fn jalali_normalize_array(dates: Vec<String>) -> Vec<Option<String>> {
+    dates
+        .iter()
+        .map(|x| {
+            jalali_date_try_parse(x.trim())
+                .ok()
+                .map(|x| jalali_date_to_string(&x))
+        })
+        .collect()
+}
+
+#[pg_extern]
+fn jalali_assert_weekday(date: &str, weekday: &str) -> bool {
+    jalali_weekday_index(&jalali_date_parse(date)) == jalali_weekday_parse(weekday)
+}
+
+/// Jalali dates in the given year on which a Hijri month/day falls, using the arithmetic
+/// (civil) Islamic calendar. Officially announced dates may differ by a day since they
+/// follow moon sighting.
+#[pg_extern]
+fn hijri_holiday_in_jalali(hijri_month: i32, hijri_day: i32, jalali_year: i32) -> Vec<String> {
+    if !(1..=12).contains(&hijri_month) || !(1..=30).contains(&hijri_day) {
+        invalid_parameter_error(format!("invalid hijri date {hijri_month}/{hijri_day}"));
+    }
+    let year_start = jalali_date_clamped(jalali_year, 1, 1).to_calendar(IslamicCivil);
+    let year_end = jalali_date_clamped(jalali_year, 12, 30).to_calendar(IslamicCivil);
+
+    (year_start.year().number..=year_end.year().number)
+        .filter_map(|x| {
+            Date::try_new_islamic_civil_date_with_calendar(
+                x,
+                hijri_month as u8,
+                hijri_day as u8,
+                IslamicCivil,
+            )
+            .ok()
+        })
+        .map(|x| x.to_calendar(Persian))
+        .filter(|x| x.year().number == jalali_year)
+        .map(|x| jalali_date_to_string(&x))
+        .collect()
+}
+
+/// Calendar-aware years/months/days between two dates, borrowing days from the month
+/// preceding the end date; `invert` is set when the end precedes the start.
+fn jalali_date_diff_ymd_internal(
+    start: &Date<Persian>,
+    end: &Date<Persian>,
+) -> (i32, i32, i32, bool) {
+    let (first, last, invert) = if start <= end {
+        (start, end, false)
+    } else {
+        (end, start, true)
+    };
+
+    let mut years = last.year().number - first.year().number;
+    let mut months = last.month().ordinal as i32 - first.month().ordinal as i32;
+    let mut days = last.day_of_month().0 as i32 - first.day_of_month().0 as i32;
+
+    if days < 0 {
+        months -= 1;
+        let (year, month) = if last.month().ordinal == 1 {
+            (last.year().number - 1, 12)
+        } else {
+            (last.year().number, last.month().ordinal - 1)
+        };
+        days += jalali_days_in_month(year, month) as i32;
+    }
+    if months < 0 {
+        years -= 1;
+        months += 12;
+    }
+    (years, months, days, invert)
+}
+
+#[pg_extern]
+fn jalali_diff_ymd(
+    date_start: &str,
+    date_end: &str,
+) -> TableIterator<'static, (name!(years, i32), name!(months, i32), name!(days, i32))> {
+    TableIterator::once(jalali_date_diff_ymd_signed(
+        &jalali_date_parse(date_start),
+        &jalali_date_parse(date_end),
+    ))
+}
+
+/// Years, months and days from `start` to `end`, all negative when `end` precedes `start`.
+fn jalali_date_diff_ymd_signed(start: &Date<Persian>, end: &Date<Persian>) -> (i32, i32, i32) {
+    let (years, months, days, invert) = jalali_date_diff_ymd_internal(start, end);
+    let sign = if invert { -1 } else { 1 };
+    (years * sign, months * sign, days * sign)
+}
+
+#[pg_extern]
+fn jalali_parse_dmy(date: &str) -> String {
+    let mut fields = date_fields_split(date);
+    fields.reverse();
+    let (year, month, day) = match date_fields_try_parse(&fields.join("/")) {
+        Ok(x) => x,
+        Err(e) => invalid_datetime_error(e.message(date)),
+    };
+    match Date::try_new_persian_date(year, month, day) {
+        Ok(x) => jalali_date_to_string(&x),
+        _ => invalid_datetime_error(format!("invalid date {date} day-first jalali date")),
+    }
+}
+
+/// Whether the Gregorian equivalent fits PostgreSQL's `date` range, 4714-11-24 BC
+/// (extended year -4713) through 5874897-12-31 AD.
+fn iso_date_in_pg_range(date: &Date<Iso>) -> bool {
+    let key = (
+        date.year().number,
+        date.month().ordinal,
+        date.day_of_month().0,
+    );
+    ((-4713, 11, 24)..=(5874897, 12, 31)).contains(&key)
+}
+
+/// Next date on or after `date` whose day of month is `target_day`; months shorter than
+/// `target_day` use their last day instead.
+#[pg_extern]
+fn jalali_next_day_of_month(date: &str, target_day: i32) -> String {
+    if !(1..=31).contains(&target_day) {
+        invalid_parameter_error(format!("invalid target day {target_day}"));
+    }
+    let date_value = jalali_date_parse(date);
+    let year = date_value.year().number;
+    let month = date_value.month().ordinal;
+
+    let next = jalali_date_clamped(year, month, target_day as u32);
+    if next >= date_value {
+        return jalali_date_to_string(&next);
+    }
+    let (year, month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    jalali_date_to_string(&jalali_date_clamped(year, month, target_day as u32))
+}
+
+#[pg_extern]
+fn jalali_business_calendar(
+    start: &str,
+    end: &str,
+    holidays: Vec<String>,
+) -> TableIterator<
+    'static,
+    (
+        name!(date, String),
+        name!(is_working, bool),
+        name!(weekday, String),
+    ),
+> {
+    let holidays = jalali_holidays_parse(&holidays);
+    let rows: Vec<_> = jalali_date_range(start, end)
+        .map(|x| {
+            (
+                jalali_date_to_string(&x),
+                jalali_is_working_day(&x, &holidays),
+                JALALI_WEEKDAY_NAMES[jalali_weekday_index(&x)].to_string(),
+            )
+        })
+        .collect();
+    TableIterator::new(rows)
+}
+
+#[pg_extern]
+fn jalali_month_name_dari(date: &str) -> String {
+    DARI_MONTH_NAMES[jalali_date_parse(date).month().ordinal as usize - 1].to_string()
+}
+
+/// Parses a written date such as `28 مرداد 1403`, accepting Persian or Dari month names.
+#[pg_extern]
+fn jalali_parse_written(s: &str) -> String {
+    let splitted: Vec<&str> = s.split_whitespace().collect();
+    if splitted.len() != 3 {
+        invalid_datetime_error(format!("invalid date {s} format"));
+    }
+    let month = match JALALI_MONTH_NAMES
+        .iter()
+        .position(|x| *x == splitted[1])
+        .or_else(|| DARI_MONTH_NAMES.iter().position(|x| *x == splitted[1]))
+    {
+        Some(x) => x + 1,
+        None => invalid_datetime_error(format!("invalid date {s} month name")),
+    };
+    jalali_date_to_string(&jalali_date_parse(&format!(
+        "{}/{}/{}",
+        splitted[2], month, splitted[0]
+    )))
+}
+
+fn timezone_parse(tz: &str) -> Tz {
+    match tz.parse::<Tz>() {
+        Ok(x) => x,
+        _ => invalid_parameter_error(format!("invalid time zone {tz}")),
+    }
+}
+
+/// UTC offset in effect in `tz` at noon UTC of the date's Gregorian equivalent.
+#[pg_extern]
+fn jalali_tz_offset(date: &str, tz: &str) -> String {
+    let noon = jalali_date_to_naive(date).and_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap());
+    timezone_parse(tz)
+        .offset_from_utc_datetime(&noon)
+        .fix()
+        .to_string()
+}
+
+/// Offset from Farvardin 1 to the Saturday starting week 1, the first week whose Tuesday falls
+/// in the year (the four-day rule of jalali_weeks_in_year).
+fn jalali_first_week_offset(year: i32) -> i32 {
+    let first_weekday = jalali_weekday_index(&jalali_date_clamped(year, 1, 1)) as i32;
+    (3 - first_weekday).rem_euclid(7) - 3
+}
+
+/// Tuesday of the Saturday-start week holding the date; its year is the week-numbering year.
+fn jalali_week_tuesday(date: &str) -> Date<Persian> {
+    let date_value = jalali_date_parse(date);
+    jalali_date_add_days_internal(date, 3 - jalali_weekday_index(&date_value) as i32)
+}
+
+/// Week of year with Saturday-start weeks under the same four-day rule as
+/// jalali_weeks_in_year: days before week 1 belong to the last week of the previous year and
+/// days after the last week to week 1 of the next year, as reported by jalali_week_year.
+#[pg_extern]
+fn jalali_week_of_year(date: &str) -> i32 {
+    (jalali_week_tuesday(date).day_of_year_info().day_of_year as i32 - 1) / 7 + 1
+}
+
+/// Week-numbering year that jalali_week_of_year counts the date's week in. It differs from the
+/// calendar year only for the first or last few days of a year.
+#[pg_extern]
+fn jalali_week_year(date: &str) -> i32 {
+    jalali_week_tuesday(date).year().number
+}
+
+/// Date of the weekday in the given week, where `year` is the week-numbering year from
+/// jalali_week_year, so week 1 may start in the last days of the previous Esfand.
+#[pg_extern]
+fn jalali_date_from_week(year: i32, week: i32, weekday: &str) -> String {
+    let weekday = jalali_weekday_parse(weekday) as i32;
+    if !(1..=jalali_weeks_in_year(year)).contains(&week) {
+        invalid_parameter_error(format!("invalid week {week} for year {year}"));
+    }
+
+    let year_start = jalali_date_to_string(&jalali_date_clamped(year, 1, 1));
+    jalali_date_to_string(&jalali_date_add_days_internal(
+        &year_start,
+        jalali_first_week_offset(year) + (week - 1) * 7 + weekday,
+    ))
+}
+
+#[pg_extern]
+fn jalali_date_diff_abs(date_start: &str, date_end: &str) -> i32 {
+    jalali_date_diff(date_start, date_end).abs()
+}
+
+/// Julian calendar equivalent for dates whose proleptic Gregorian equivalent precedes
+/// `julian_before`, Gregorian otherwise.
+#[pg_extern]
+fn jalali_to_gregorian_cutoff(date: &str, julian_before: default!(&str, "'1582-10-15'")) -> String {
+    let cutoff = match NaiveDate::parse_from_str(julian_before, "%Y-%m-%d") {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid date {julian_before} cutoff")),
+    };
+    if jalali_date_to_naive(date) >= cutoff {
+        return jalali_date_to_gregorian(date);
+    }
+
+    let julian_date = jalali_date_parse(date).to_calendar(Julian);
+    let year = if julian_date.year().era.0.as_str() == "bce" {
+        1 - julian_date.year().number
+    } else {
+        julian_date.year().number
+    };
+    format!(
+        "{}-{:0>2}-{:0>2}",
+        format_year(year),
+        julian_date.month().ordinal,
+        julian_date.day_of_month().0,
+    )
+}
+
+#[pg_extern]
+fn jalali_round_trip_ok(date: &str) -> bool {
+    let date_value = match jalali_date_try_parse(date) {
+        Ok(x) => x,
+        _ => return false,
+    };
+    let canonical = jalali_date_to_string(&date_value);
+    match gregorian_date_try_parse(&jalali_date_to_gregorian(&canonical)) {
+        Ok(x) => jalali_date_to_string(&x.to_calendar(Persian)) == canonical,
+        _ => false,
+    }
+}
+
+#[pg_extern]
+fn jalali_add_days_or_null(date: &str, days: i32) -> Option<String> {
+    jalali_date_try_add_days(date, days)
+        .ok()
+        .map(|x| jalali_date_to_string(&x))
+}
+
+const PERSIAN_ONES: [&str; 10] = [
+    "", "یک", "دو", "سه", "چهار", "پنج", "شش", "هفت", "هشت", "نه",
+];
+
+const PERSIAN_TEENS: [&str; 10] = [
+    "ده",
+    "یازده",
+    "دوازده",
+    "سیزده",
+    "چهارده",
+    "پانزده",
+    "شانزده",
+    "هفده",
+    "هجده",
+    "نوزده",
+];
+
+const PERSIAN_TENS: [&str; 10] = [
+    "",
+    "",
+    "بیست",
+    "سی",
+    "چهل",
+    "پنجاه",
+    "شصت",
+    "هفتاد",
+    "هشتاد",
+    "نود",
+];
+
+const PERSIAN_HUNDREDS: [&str; 10] = [
+    "",
+    "یکصد",
+    "دویست",
+    "سیصد",
+    "چهارصد",
+    "پانصد",
+    "ششصد",
+    "هفتصد",
+    "هشتصد",
+    "نهصد",
+];
+
+fn persian_number_words(number: u32) -> String {
+    if number == 0 {
+        return "صفر".to_string();
+    }
+
+    let mut parts = Vec::new();
+    for (scale, name) in [
+        (1_000_000_000, "میلیارد"),
+        (1_000_000, "میلیون"),
+        (1_000, "هزار"),
+    ] {
+        let chunk = number / scale % 1000;
+        if chunk > 0 {
+            parts.push(format!("{} {name}", persian_number_words(chunk)));
+        }
+    }
+
+    let rest = number % 1000;
+    if rest >= 100 {
+        parts.push(PERSIAN_HUNDREDS[rest as usize / 100].to_string());
+    }
+    match rest % 100 {
+        0 => {}
+        x @ 10..=19 => parts.push(PERSIAN_TEENS[x as usize - 10].to_string()),
+        x => {
+            if x >= 20 {
+                parts.push(PERSIAN_TENS[x as usize / 10].to_string());
+            }
+            if x % 10 > 0 {
+                parts.push(PERSIAN_ONES[x as usize % 10].to_string());
+            }
+        }
+    }
+    parts.join(" و ")
+}
+
+fn persian_ordinal_words(number: u32) -> String {
+    let words = persian_number_words(number);
+    if let Some(x) = words.strip_suffix("سه") {
+        format!("{x}سوم")
+    } else if words.ends_with('ی') {
+        format!("{words}\u{200c}ام")
+    } else {
+        format!("{words}م")
+    }
+}
+
+fn jalali_date_to_words_internal(date: &Date<Persian>) -> String {
+    let year = date.year().number;
+    format!(
+        "{} {} {}{}",
+        persian_ordinal_words(date.day_of_month().0),
+        JALALI_MONTH_NAMES[date.month().ordinal as usize - 1],
+        if year < 0 { "منفی " } else { "" },
+        persian_number_words(year.unsigned_abs())
+    )
+}
+
+#[pg_extern]
+fn jalali_date_to_words(date: &str) -> String {
+    jalali_date_to_words_internal(&jalali_date_parse(date))
+}
+
+fn jalali_date_now_in_tz(tz: &str) -> Date<Persian> {
+    naive_date_to_jalali(Utc::now().with_timezone(&timezone_parse(tz)).date_naive())
+}
+
+#[pg_extern]
+fn jalali_date_now_tz(tz: &str) -> String {
+    jalali_date_to_string(&jalali_date_now_in_tz(tz))
+}
+
+#[pg_extern]
+fn jalali_today_in_words(tz: &str) -> String {
+    jalali_date_to_words_internal(&jalali_date_now_in_tz(tz))
+}
+
+#[pg_extern]
+fn gregorian_is_leap_for_jalali(date: &str) -> bool {
+    jalali_date_to_gregorian_internal(date)
+        .to_calendar(Gregorian)
+        .is_in_leap_year()
+}
+
+#[pg_extern]
+fn jalali_interval_has_weekday(date_start: &str, date_end: &str, weekday: &str) -> bool {
+    let weekday_index = jalali_weekday_parse(weekday);
+    jalali_date_range(date_start, date_end).any(|x| jalali_weekday_index(&x) == weekday_index)
+}
+
+#[pg_extern]
+fn jalali_shift_weeks_preserving_weekday(date: &str, weeks: i32) -> String {
+    let days = match weeks.checked_mul(7) {
+        Some(x) => x,
+        None => invalid_parameter_error(format!("invalid weeks {weeks}")),
+    };
+    jalali_date_to_string(&jalali_date_add_days_internal(date, days))
+}
+
+const JALALI_FIXED_HOLIDAYS: [(u8, u8, &str); 10] = [
+    (1, 1, "عید نوروز"),
+    (1, 2, "عید نوروز"),
+    (1, 3, "عید نوروز"),
+    (1, 4, "عید نوروز"),
+    (1, 12, "روز جمهوری اسلامی"),
+    (1, 13, "روز طبیعت"),
+    (3, 14, "رحلت امام خمینی"),
+    (3, 15, "قیام ۱۵ خرداد"),
+    (11, 22, "پیروزی انقلاب اسلامی"),
+    (12, 29, "ملی شدن صنعت نفت"),
+];
+
+#[pg_extern]
+fn jalali_fixed_holidays_in_year(
+    year: i32,
+) -> TableIterator<'static, (name!(date, String), name!(name, String))> {
+    let rows: Vec<(String, String)> = JALALI_FIXED_HOLIDAYS
+        .iter()
+        .map(
+            |(month, day, name)| match Date::try_new_persian_date(year, *month, *day) {
+                Ok(x) => (jalali_date_to_string(&x), name.to_string()),
+                _ => invalid_parameter_error(format!("invalid year {year}")),
+            },
+        )
+        .collect();
+    TableIterator::new(rows)
+}
+
+const JALALI_ERA_MARKERS: [&str; 4] = ["ه.ش", "هش", "ap", "a.p."];
+
+#[pg_extern]
+fn jalali_parse_with_era(s: &str) -> String {
+    let trimmed = s.trim();
+    let (date, marker) = match trimmed.split_once(char::is_whitespace) {
+        Some((date, marker)) => (date, marker.trim()),
+        None => (trimmed, ""),
+    };
+    let normalized: String = marker
+        .chars()
+        .filter(|x| !x.is_whitespace() && !['\u{200c}', '\u{200d}', 'ـ'].contains(x))
+        .collect::<String>()
+        .to_lowercase();
+    if !normalized.is_empty() && !JALALI_ERA_MARKERS.contains(&normalized.as_str()) {
+        invalid_datetime_error(format!("invalid date {s} era marker {marker}"));
+    }
+    jalali_date_to_string(&jalali_date_parse(date))
+}
+
+#[pg_extern]
+fn jalali_date_add_months(date: &str, months: i32) -> String {
+    jalali_date_to_string(&jalali_date_add_months_internal(date, months, true))
+}
+
+#[pg_extern]
+fn jalali_datetime_diff_seconds(start_dt: &str, end_dt: &str) -> i64 {
+    let (start_date, start_time) = jalali_datetime_parse(start_dt);
+    let (end_date, end_time) = jalali_datetime_parse(end_dt);
+    let start = jalali_to_naive_date(&start_date).and_time(start_time);
+    let end = jalali_to_naive_date(&end_date).and_time(end_time);
+    (end - start).num_seconds()
+}
+
+#[pg_extern]
+fn jalali_to_gregorian_fmt(date: &str, fmt: &str) -> String {
+    let items: Vec<Item> = StrftimeItems::new(fmt).collect();
+    if items.contains(&Item::Error) {
+        invalid_parameter_error(format!("invalid pattern {fmt}"));
+    }
+
+    let mut result = String::new();
+    match write!(
+        result,
+        "{}",
+        jalali_date_to_naive(date).format_with_items(items.iter())
+    ) {
+        Ok(_) => result,
+        _ => invalid_parameter_error(format!("invalid pattern {fmt} for date")),
+    }
+}
+
+#[pg_extern]
+fn jalali_date_weekday(date: &str) -> String {
+    JALALI_WEEKDAY_NAMES[jalali_weekday_index(&jalali_date_parse(date))].to_string()
+}
+
+#[pg_extern]
+fn jalali_date_weekday_num(date: &str) -> i32 {
+    jalali_weekday_index(&jalali_date_parse(date)) as i32
+}
+
+/// PostgreSQL's `timestamp` shares the `date` lower bound but stops at 294276-12-31 AD.
+#[pg_extern]
+fn jalali_datetime_representable(datetime: &str) -> bool {
+    match jalali_datetime_try_parse(datetime) {
+        Ok((date, _)) => {
+            let iso_date = date.to_iso();
+            iso_date_in_pg_range(&iso_date) && iso_date.year().number <= 294276
+        }
+        _ => false,
+    }
+}
+
+#[pg_extern]
+fn jalali_date_diff_detailed(
+    date_start: &str,
+    date_end: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(years, i32),
+        name!(months, i32),
+        name!(days, i32),
+        name!(total_days, i32),
+        name!(invert, bool),
+    ),
+> {
+    let start = jalali_date_parse(date_start);
+    let end = jalali_date_parse(date_end);
+    let (years, months, days) = jalali_date_diff_ymd_signed(&start, &end);
+    TableIterator::once((
+        years,
+        months,
+        days,
+        jalali_date_diff(date_start, date_end),
+        end < start,
+    ))
+}
+
+/// PostgreSQL numbers BC years from -1 while ISO uses astronomical years, so 1 BC is
+/// -1 there and 0 here.
+#[pg_extern]
+fn gregorian_to_jalali(d: pgrx::datum::Date) -> String {
+    if d.is_infinity() || d.is_neg_infinity() {
+        invalid_datetime_error("invalid date infinity".to_string());
+    }
+    let year = if d.year() < 0 { d.year() + 1 } else { d.year() };
+    match Date::try_new_iso_date(year, d.month(), d.day()) {
+        Ok(x) => jalali_date_to_string(&x.to_calendar(Persian)),
+        _ => invalid_datetime_error(format!(
+            "invalid date {}-{}-{} new jalali date",
+            d.year(),
+            d.month(),
+            d.day()
+        )),
+    }
+}
+
+#[pg_extern]
+fn jalali_to_date(jalali: &str) -> pgrx::datum::Date {
+    let iso_date = jalali_date_to_gregorian_internal(jalali);
+    if !iso_date_in_pg_range(&iso_date) {
+        invalid_datetime_error(format!("invalid date {jalali} out of range"));
+    }
+    let year = iso_date.year().number;
+    match pgrx::datum::Date::new(
+        if year <= 0 { year - 1 } else { year },
+        iso_date.month().ordinal as u8,
+        iso_date.day_of_month().0 as u8,
+    ) {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid date {jalali} out of range")),
+    }
+}
+
+/// Signed days from `date` to `weekday` within the Saturday-start week holding `date`. The
+/// result never leaves that week, so it spans -6..=6 (Friday to Saturday is -6) rather than
+/// jumping to a closer occurrence in the neighbouring week.
+#[pg_extern]
+fn jalali_weekday_offset(date: &str, weekday: &str) -> i32 {
+    let current = jalali_weekday_index(&jalali_date_parse(date)) as i32;
+    jalali_weekday_parse(weekday) as i32 - current
+}
+
+#[pg_extern]
+fn generate_jalali_series(
+    start: &str,
+    end: &str,
+    step_days: i32,
+) -> SetOfIterator<'static, String> {
+    if step_days == 0 {
+        invalid_parameter_error("invalid step 0".to_string());
+    }
+    let start_day = jalali_day_number(&jalali_date_parse(start));
+    let end_day = jalali_day_number(&jalali_date_parse(end));
+    SetOfIterator::new(
+        std::iter::successors(Some(start_day), move |x| x.checked_add(step_days as i64))
+            .take_while(move |x| {
+                if step_days > 0 {
+                    *x <= end_day
+                } else {
+                    *x >= end_day
+                }
+            })
+            .map_while(jalali_date_from_day_number)
+            .map(|x| jalali_date_to_string(&x)),
+    )
+}
+
+fn take_digits(s: &str, max: usize) -> Option<(u32, &str)> {
+    let length = s
+        .chars()
+        .take(max)
+        .take_while(|x| x.is_ascii_digit())
+        .count();
+    if length == 0 {
+        return None;
+    }
+    Some((s[..length].parse().ok()?, &s[length..]))
+}
+
+/// Parses `s` against a pattern using the `%Y`, `%m`, `%d`, `%B` and `%%` specifiers of
+/// `jalali_date_format`; any other character must match literally.
+fn jalali_date_parse_with_format(s: &str, pattern: &str) -> Option<Date<Persian>> {
+    let (mut year, mut month, mut day) = (None, None, None);
+    let mut rest = s;
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            rest = rest.strip_prefix(c)?;
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => {
+                let (negative, unsigned) = match rest.strip_prefix('-') {
+                    Some(x) => (true, x),
+                    None => (false, rest),
+                };
+                let (value, remaining) = take_digits(unsigned, 9)?;
+                year = Some(if negative {
+                    -(value as i32)
+                } else {
+                    value as i32
+                });
+                rest = remaining;
+            }
+            Some('m') => {
+                let (value, remaining) = take_digits(rest, 2)?;
+                month = Some(value);
+                rest = remaining;
+            }
+            Some('d') => {
+                let (value, remaining) = take_digits(rest, 2)?;
+                day = Some(value);
+                rest = remaining;
+            }
+            Some('B') => {
+                let index = JALALI_MONTH_NAMES
+                    .iter()
+                    .position(|x| rest.starts_with(x))?;
+                month = Some(index as u32 + 1);
+                rest = &rest[JALALI_MONTH_NAMES[index].len()..];
+            }
+            Some('%') => rest = rest.strip_prefix('%')?,
+            Some(x) => invalid_parameter_error(format!("invalid pattern {pattern} specifier %{x}")),
+            None => invalid_parameter_error(format!("invalid pattern {pattern} trailing %")),
+        }
+    }
+
+    if !rest.is_empty() {
+        return None;
+    }
+    Date::try_new_persian_date(year?, u8::try_from(month?).ok()?, u8::try_from(day?).ok()?).ok()
+}
+
+#[pg_extern]
+fn jalali_parse_any(s: &str, formats: Vec<String>) -> Option<String> {
+    formats
+        .iter()
+        .find_map(|x| jalali_date_parse_with_format(&digits_to_ascii(s.trim()), x))
+        .map(|x| jalali_date_to_string(&x))
+}
+
+#[pg_extern]
+fn jalali_date_start_of_month(date: &str) -> String {
+    let date_value = jalali_date_parse(date);
+    jalali_date_to_string(&jalali_date_clamped(
+        date_value.year().number,
+        date_value.month().ordinal,
+        1,
+    ))
+}
+
+#[pg_extern]
+fn jalali_date_end_of_month(date: &str) -> String {
+    let date_value = jalali_date_parse(date);
+    let (year, month) = (date_value.year().number, date_value.month().ordinal);
+    jalali_date_to_string(&jalali_date_clamped(
+        year,
+        month,
+        jalali_days_in_month(year, month),
+    ))
+}
+
+#[pg_extern]
+fn jalali_date_start_of_year(date: &str) -> String {
+    jalali_date_to_string(&jalali_date_clamped(
+        jalali_date_parse(date).year().number,
+        1,
+        1,
+    ))
+}
+
+#[pg_extern]
+fn jalali_date_end_of_year(date: &str) -> String {
+    let year = jalali_date_parse(date).year().number;
+    jalali_date_to_string(&jalali_date_clamped(
+        year,
+        12,
+        jalali_days_in_month(year, 12),
+    ))
+}
+
+#[pg_extern]
+fn jalali_weekday_name(date: &str, locale: &str) -> String {
+    let names = match locale {
+        "fa" => JALALI_WEEKDAY_NAMES,
+        "en" => ENGLISH_WEEKDAY_NAMES,
+        "ar" => ARABIC_WEEKDAY_NAMES,
+        _ => invalid_parameter_error(format!("invalid locale {locale}")),
+    };
+    names[jalali_weekday_index(&jalali_date_parse(date))].to_string()
+}
+
+#[pg_extern]
+fn jalali_month_name_l(date: &str, locale: &str) -> String {
+    let names = match locale {
+        "fa" => JALALI_MONTH_NAMES,
+        "en" => ENGLISH_MONTH_NAMES,
+        "ar" => ARABIC_MONTH_NAMES,
+        "dari" => DARI_MONTH_NAMES,
+        _ => invalid_parameter_error(format!("invalid locale {locale}")),
+    };
+    names[jalali_date_parse(date).month().ordinal as usize - 1].to_string()
+}
+
+#[pg_extern]
+fn jalali_weekday_count_array(date_start: &str, date_end: &str) -> Vec<i32> {
+    let mut counts = vec![0; 7];
+    for date in jalali_date_range(date_start, date_end) {
+        counts[jalali_weekday_index(&date)] += 1;
+    }
+    counts
+}
+
+/// First working day reached by repeatedly moving `step` days from `date`.
+fn jalali_business_day_from(date: &str, holidays: &[String], step: i32) -> String {
+    let holidays = jalali_holidays_parse(holidays);
+    let mut current = jalali_date_add_days_internal(date, step);
+    while !jalali_is_working_day(&current, &holidays) {
+        current = jalali_date_add_days_internal(&jalali_date_to_string(&current), step);
+    }
+    jalali_date_to_string(&current)
+}
+
+#[pg_extern]
+fn jalali_previous_business_day(
+    date: &str,
+    holidays: default!(Vec<String>, "ARRAY[]::text[]"),
+) -> String {
+    jalali_business_day_from(date, &holidays, -1)
+}
+
+#[pg_extern]
+fn jalali_next_business_day(date: &str, holidays: Vec<String>) -> String {
+    jalali_business_day_from(date, &holidays, 1)
+}
+
+/// Rewrites ASCII digits as Persian digits for `fa` and leaves them untouched for `latin`.
+fn digits_localize(s: &str, digits: &str) -> String {
+    match digits {
+        "latin" => s.to_string(),
+        "fa" => s
+            .chars()
+            .map(|x| match x.to_digit(10) {
+                Some(d) if x.is_ascii_digit() => char::from_u32('۰' as u32 + d).unwrap_or(x),
+                _ => x,
+            })
+            .collect(),
+        _ => invalid_parameter_error(format!("invalid digits {digits}")),
+    }
+}
+
+#[pg_extern]
+fn jalali_date_now_digits(digits: &str) -> String {
+    digits_localize(&jalali_date_now(), digits)
+}
+
+fn time_parse(time: &str) -> NaiveTime {
+    match NaiveTime::parse_from_str(time.trim(), "%H:%M") {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid time {time}")),
+    }
+}
+
+/// A window whose start is after its end wraps past midnight.
+#[pg_extern]
+fn jalali_time_in_window(datetime: &str, start_time: &str, end_time: &str) -> bool {
+    let (_, time) = jalali_datetime_parse(datetime);
+    let start = time_parse(start_time);
+    let end = time_parse(end_time);
+    if start <= end {
+        start <= time && time < end
+    } else {
+        start <= time || time < end
+    }
+}
+
+#[pg_extern]
+fn jalali_end_of_previous_month(date: &str) -> String {
+    jalali_date_add_days(&jalali_date_start_of_month(date), -1)
+}
+
+#[pg_extern]
+fn jalali_start_of_next_month(date: &str) -> String {
+    jalali_date_add_days(&jalali_date_end_of_month(date), 1)
+}
+
+const PERSIAN_QUARTER_ORDINALS: [&str; 4] = ["اول", "دوم", "سوم", "چهارم"];
+
+#[pg_extern]
+fn jalali_quarter_label(date: &str, digits: default!(&str, "'fa'")) -> String {
+    let date_value = jalali_date_parse(date);
+    format!(
+        "فصل {} {}",
+        PERSIAN_QUARTER_ORDINALS[(date_value.month().ordinal as usize - 1) / 3],
+        digits_localize(&date_value.year().number.to_string(), digits)
+    )
+}
+
+/// Number of month boundaries between the dates, ignoring the day of month. Persian and
+/// Gregorian months start on different days, so a pair within one Persian month can span
+/// two Gregorian months and vice versa.
+#[pg_extern]
+fn jalali_date_diff_months_calendar(
+    date_start: &str,
+    date_end: &str,
+    calendar: default!(&str, "'persian'"),
+) -> i32 {
+    let start = jalali_date_parse(date_start);
+    let end = jalali_date_parse(date_end);
+    let month_index = |year: i32, month: u32| year as i64 * 12 + month as i64;
+    let months = match calendar {
+        "persian" => {
+            month_index(end.year().number, end.month().ordinal)
+                - month_index(start.year().number, start.month().ordinal)
+        }
+        "gregorian" => {
+            let (start, end) = (start.to_iso(), end.to_iso());
+            month_index(end.year().number, end.month().ordinal)
+                - month_index(start.year().number, start.month().ordinal)
+        }
+        _ => invalid_parameter_error(format!("invalid calendar {calendar}")),
+    };
+    match i32::try_from(months) {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!(
+            "invalid date {date_start} {date_end} result overflow"
+        )),
+    }
+}
+
+/// First instant of the local day in `tz`. When midnight falls in a DST gap the day starts
+/// at the transition itself, i.e. midnight read with the offset in effect just before it.
+fn local_day_start_utc(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    match tz.from_local_datetime(&midnight) {
+        MappedLocalTime::Single(x) | MappedLocalTime::Ambiguous(x, _) => x.with_timezone(&Utc),
+        MappedLocalTime::None => {
+            let offset = tz
+                .offset_from_utc_datetime(&(midnight - TimeDelta::days(1)))
+                .fix();
+            (midnight - TimeDelta::seconds(offset.local_minus_utc() as i64)).and_utc()
+        }
+    }
+}
+
+fn jalali_day_bounds_utc(date: &str, tz: &str) -> (DateTime<Utc>, DateTime<Utc>) {
+    let tz = timezone_parse(tz);
+    let day = jalali_date_to_naive(date);
+    let next_day = match day.succ_opt() {
+        Some(x) => x,
+        None => invalid_datetime_error(format!("invalid date {date} add day")),
+    };
+    (
+        local_day_start_utc(day, tz),
+        local_day_start_utc(next_day, tz),
+    )
+}
+
+fn timestamptz_from_utc(date_time: DateTime<Utc>) -> TimestampWithTimeZone {
+    // PostgreSQL counts microseconds from 2000-01-01 00:00:00 UTC.
+    match TimestampWithTimeZone::try_from(date_time.timestamp_micros() - 946_684_800_000_000) {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid timestamp {date_time} out of range")),
+    }
+}
+
+#[pg_extern]
+fn jalali_day_range_tz(date: &str, tz: &str) -> Range<TimestampWithTimeZone> {
+    let (start, end) = jalali_day_bounds_utc(date, tz);
+    Range::new(
+        RangeBound::Inclusive(timestamptz_from_utc(start)),
+        RangeBound::Exclusive(timestamptz_from_utc(end)),
+    )
+}
+
+/// Reads `YYYY-MM-DD` as Gregorian and `YYYY/MM/DD` as Jalali; unlike `any_date_to_jalali`
+/// only the separator decides, so any year is accepted.
+#[pg_extern]
+fn smart_to_jalali(s: &str) -> String {
+    let s = s.trim();
+    let unsigned = s.strip_prefix('-').unwrap_or(s);
+    match (unsigned.contains('/'), unsigned.contains('-')) {
+        (true, false) => jalali_date_to_string(&jalali_date_parse(s)),
+        (false, true) => gregorian_date_to_jalali(s),
+        _ => invalid_datetime_error(format!("invalid date {s} ambiguous calendar")),
+    }
+}
+
+#[pg_extern]
+fn jalali_month_weekday_counts(year: i32, month: i32) -> Vec<i32> {
+    if !(1..=12).contains(&month) {
+        invalid_parameter_error(format!("invalid month {month}"));
+    }
+    let length = jalali_days_in_month(year, month as u32) as usize;
+    let first_weekday = jalali_weekday_index(&jalali_date_clamped(year, month as u32, 1));
+    (0..7)
+        .map(|x| (length / 7 + usize::from((x + 7 - first_weekday) % 7 < length % 7)) as i32)
+        .collect()
+}
+
+/// Rata Die of 1970-01-01, the zero of Arrow/Parquet `date32` values.
+const UNIX_EPOCH_DAY_NUMBER: i64 = 719_163;
+
+#[pg_extern]
+fn gregorian_epoch_day_to_jalali(days_since_1970: i32) -> String {
+    match (days_since_1970 as i64 + UNIX_EPOCH_DAY_NUMBER)
+        .try_into()
+        .ok()
+        .and_then(NaiveDate::from_num_days_from_ce_opt)
+    {
+        Some(x) => jalali_date_to_string(&naive_date_to_jalali(x)),
+        None => invalid_parameter_error(format!("invalid epoch day {days_since_1970}")),
+    }
+}
+
+#[pg_extern]
+fn jalali_to_epoch_day(date: &str) -> i32 {
+    match i32::try_from(jalali_day_number(&jalali_date_parse(date)) - UNIX_EPOCH_DAY_NUMBER) {
+        Ok(x) => x,
+        _ => invalid_datetime_error(format!("invalid date {date} epoch day overflow")),
+    }
+}
+
+#[pg_extern]
+fn jalali_gregorian_week(date: &str) -> i32 {
+    jalali_date_to_naive(date).iso_week().week() as i32
+}
+
+#[pg_extern]
+fn jalali_is_leap_day(date: &str) -> bool {
+    let date_value = jalali_date_parse(date);
+    date_value.month().ordinal == 12 && date_value.day_of_month().0 == 30
+}
+
+#[pg_extern]
+fn jalali_add_days_format(date: &str, days: i32, pattern: &str) -> String {
+    jalali_date_format_internal(&jalali_date_add_days_internal(date, days), pattern)
+}
+
+#[pg_extern]
+fn jalali_sort_array(dates: Vec<String>) -> Vec<String> {
+    let mut keyed: Vec<(Option<Date<Persian>>, String)> = dates
+        .into_iter()
+        .map(|x| (jalali_date_try_parse(&x).ok(), x))
+        .collect();
+    keyed.sort_by_key(|(x, _)| (x.is_none(), *x));
+    keyed.into_iter().map(|(_, x)| x).collect()
+}
+
+#[pg_extern]
+fn jalali_dedupe_array(dates: Vec<String>) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    dates
+        .into_iter()
+        .filter(|x| match jalali_date_try_parse(x) {
+            Ok(date) => seen.insert(date),
+            _ => false,
+        })
+        .collect()
+}
+
+#[pg_extern]
+fn jalali_quarter_bounds(
+    date: &str,
+) -> TableIterator<'static, (name!(start, String), name!(end, String))> {
+    let date_value = jalali_date_parse(date);
+    let year = date_value.year().number;
+    let first_month = (date_value.month().ordinal - 1) / 3 * 3 + 1;
+    let last_month = first_month + 2;
+    TableIterator::once((
+        jalali_date_to_string(&jalali_date_clamped(year, first_month, 1)),
+        jalali_date_to_string(&jalali_date_clamped(
+            year,
+            last_month,
+            jalali_days_in_month(year, last_month),
+        )),
+    ))
+}
+
+#[pg_extern]
+fn jalali_all_workdays(date_start: &str, date_end: &str, holidays: Vec<String>) -> bool {
+    let holidays = jalali_holidays_parse(&holidays);
+    jalali_date_range(date_start, date_end).all(|x| jalali_is_working_day(&x, &holidays))
+}
+
+#[pg_extern]
+fn jalali_diff_excluding(date_start: &str, date_end: &str, excluded: Vec<String>) -> i32 {
+    let excluded = jalali_holidays_parse(&excluded);
+    jalali_date_range(date_start, date_end)
+        .filter(|x| !excluded.contains(x))
+        .count() as i32
+}
+
+/// Hijri dates use the tabular civil calendar, as in `hijri_holiday_in_jalali`.
+#[pg_extern]
+fn jalali_all_calendars(
+    date: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(jalali, String),
+        name!(gregorian, String),
+        name!(hijri, String),
+    ),
+> {
+    let date_value = jalali_date_parse(date);
+    let hijri = date_value.to_calendar(IslamicCivil);
+    TableIterator::once((
+        jalali_date_to_string(&date_value),
+        jalali_date_to_gregorian(date),
+        format!(
+            "{}/{:0>2}/{:0>2}",
+            format_year(hijri.year().number),
+            hijri.month().ordinal,
+            hijri.day_of_month().0
+        ),
+    ))
+}
+
+#[pg_extern]
+fn jalali_parse_error(date: &str) -> Option<String> {
+    let (year, month, day) = match date_fields_try_parse(date) {
+        Ok(x) => x,
+        Err(e) => return Some(e.diagnostic()),
+    };
+
+    let reason = if !(1..=12).contains(&month) {
+        "month out of range"
+    } else if month == 12 && day == 30 && !jalali_is_leap_year(year) {
+        "invalid leap day"
+    } else if day == 0 || day as u32 > jalali_days_in_month(year, month as u32) {
+        "day out of range for month"
+    } else if Date::try_new_persian_date(year, month, day).is_err() {
+        "invalid jalali date"
+    } else {
+        return None;
+    };
+    Some(reason.to_string())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn test_jalali_date_add_days() {
+        assert_eq!("1403/05/30", crate::jalali_date_add_days("1403/05/28", 2));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_diff_with_addition() {
+        assert_eq!(
+            3,
+            crate::jalali_date_diff_with_addition("1403/05/28", "1403/05/30", 1)
+        );
+        assert_eq!(
+            -3,
+            crate::jalali_date_diff_with_addition("1403/05/30", "1403/05/28", 1)
+        );
+        assert_eq!(
+            i32::MAX,
+            crate::jalali_date_diff_with_addition("1403/05/28", "1403/05/29", i32::MAX - 1)
+        );
+    }
+
+    #[pg_test(error = "invalid date 1403/05/28 1403/05/29 result overflow")]
+    fn test_jalali_date_diff_with_addition_overflow() {
+        crate::jalali_date_diff_with_addition("1403/05/28", "1403/05/29", i32::MAX);
+    }
+
+    #[pg_test]
+    fn test_jalali_add_months_checked() {
+        assert_eq!(
+            "1403/07/30",
+            crate::jalali_add_months_checked("1403/06/31", 1, "clamp")
+        );
+        assert_eq!(
+            "1403/06/30",
+            crate::jalali_add_months_checked("1403/06/30", 0, "error")
+        );
+        assert_eq!(
+            "1402/10/15",
+            crate::jalali_add_months_checked("1403/01/15", -3, "error")
+        );
+    }
+
+    #[pg_test(error = "invalid date 1403/06/31 day 31 does not exist in 1403/07")]
+    fn test_jalali_add_months_checked_error() {
+        crate::jalali_add_months_checked("1403/06/31", 1, "error");
+    }
+
+    #[pg_test]
+    fn test_jalali_week_of_month_iso() {
+        assert_eq!(0, crate::jalali_week_of_month_iso("1403/06/01"));
+        assert_eq!(1, crate::jalali_week_of_month_iso("1403/06/03"));
+        assert_eq!(2, crate::jalali_week_of_month_iso("1403/06/15"));
+        assert_eq!(1, crate::jalali_week_of_month_iso("1403/05/01"));
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_tagged() {
+        assert_eq!("1403/05/28", crate::jalali_parse_tagged("fa_IR:1403/05/28"));
+        assert_eq!("1403/05/08", crate::jalali_parse_tagged("1403/5/8"));
+    }
+
+    #[pg_test(error = "invalid date en_US:1403/05/28 locale en_US")]
+    fn test_jalali_parse_tagged_unknown_locale() {
+        crate::jalali_parse_tagged("en_US:1403/05/28");
+    }
+
+    #[pg_test]
+    fn test_jalali_month_progress() {
+        assert_eq!(1.0 / 31.0, crate::jalali_month_progress("1403/01/01"));
+        assert_eq!(1.0, crate::jalali_month_progress("1403/01/31"));
+        assert_eq!(1.0, crate::jalali_month_progress("1403/12/30"));
+        assert_eq!(29.0 / 30.0, crate::jalali_month_progress("1403/12/29"));
+        assert_eq!(1.0, crate::jalali_month_progress("1402/12/29"));
+    }
+
+    #[pg_test]
+    fn test_jalali_clamp_to_year() {
+        assert_eq!(
+            "1404/12/29",
+            crate::jalali_clamp_to_year("1403/12/30", 1404)
+        );
+        assert_eq!(
+            "1399/12/30",
+            crate::jalali_clamp_to_year("1403/12/30", 1399)
+        );
+        assert_eq!(
+            "1400/05/28",
+            crate::jalali_clamp_to_year("1403/05/28", 1400)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_next_recurrence() {
+        assert_eq!(
+            "1403/08/10",
+            crate::jalali_next_recurrence("1370/08/10", "1403/05/28")
+        );
+        assert_eq!(
+            "1404/02/10",
+            crate::jalali_next_recurrence("1370/02/10", "1403/05/28")
+        );
+        assert_eq!(
+            "1403/05/28",
+            crate::jalali_next_recurrence("1370/05/28", "1403/05/28")
+        );
+        assert_eq!(
+            "1404/12/29",
+            crate::jalali_next_recurrence("1399/12/30", "1404/01/01")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_to_json() {
+        assert_eq!(
+            serde_json::json!({
+                "jalali": "1403/05/28",
+                "gregorian": "2024-08-18",
+                "weekday": "یکشنبه",
+                "is_leap": true,
+            }),
+            crate::jalali_to_json("1403/5/28").0
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_info() {
+        let rows: Vec<_> = crate::jalali_info("1403/05/28").collect();
+        assert_eq!(
+            vec![(
+                1403,
+                5,
+                28,
+                "یکشنبه".to_string(),
+                152,
+                true,
+                "2024-08-18".to_string()
+            )],
+            rows
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_epoch_millis() {
+        assert_eq!(1723939200000, crate::jalali_to_epoch_millis("1403/05/28"));
+        assert_eq!("1403/05/28", crate::epoch_millis_to_jalali(1723939200000));
+        assert_eq!(
+            "1403/05/28",
+            crate::epoch_millis_to_jalali(1723939200000 + 86399999)
+        );
+        assert_eq!(
+            "1348/10/10",
+            crate::epoch_millis_to_jalali(crate::jalali_to_epoch_millis("1348/10/10"))
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_prev_year_aligned() {
+        assert_eq!("1402/05/29", crate::jalali_prev_year_aligned("1403/05/28"));
+        assert_eq!(
+            crate::jalali_weekday_index(&crate::jalali_date_parse("1403/05/28")),
+            crate::jalali_weekday_index(&crate::jalali_date_parse("1402/05/29"))
+        );
+        assert_eq!("1402/12/24", crate::jalali_prev_year_aligned("1403/12/30"));
+    }
+
+    #[pg_test]
+    fn test_jalali_month_to_gregorian_range() {
+        assert_eq!(
+            "2024-03-20..2024-04-19",
+            crate::jalali_month_to_gregorian_range("1403/01")
+        );
+        assert_eq!(
+            "2025-02-19..2025-03-20",
+            crate::jalali_month_to_gregorian_range("1403/12")
+        );
+        assert_eq!(
+            "2024-07-22..2024-08-21",
+            crate::jalali_month_to_gregorian_range("۱۴۰۳/۰۵")
+        );
+        assert_eq!(
+            "2024-07-22..2024-08-21",
+            crate::jalali_month_to_gregorian_range("1403-5")
+        );
+    }
+
+    #[pg_test(error = "invalid month 1403/05/28 format")]
+    fn test_jalali_month_to_gregorian_range_invalid_format() {
+        crate::jalali_month_to_gregorian_range("1403/05/28");
+    }
+
+    #[pg_test(error = "invalid month 1403/13 month value")]
+    fn test_jalali_month_to_gregorian_range_invalid_month() {
+        crate::jalali_month_to_gregorian_range("1403/13");
+    }
+
+    #[pg_test]
+    fn test_jalali_date_to_gregorian_early_years() {
+        assert_eq!("2024-08-18", crate::jalali_date_to_gregorian("1403/05/28"));
+        assert_eq!("0921-03-21", crate::jalali_date_to_gregorian("300/01/01"));
+        assert_eq!("-0079-03-21", crate::jalali_date_to_gregorian("-700/01/01"));
+    }
+
+    #[pg_test]
+    fn test_jalali_weekend_only_span() {
+        assert_eq!(
+            2,
+            crate::jalali_weekend_only_span("1403/05/20", "1403/06/02", true)
+        );
+        assert_eq!(
+            4,
+            crate::jalali_weekend_only_span("1403/05/20", "1403/06/02", false)
+        );
+        assert_eq!(
+            4,
+            crate::jalali_weekend_only_span("1403/06/02", "1403/05/20", false)
+        );
+        assert_eq!(
+            0,
+            crate::jalali_weekend_only_span("1403/05/27", "1403/05/30", false)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_date_trunc_week() {
+        assert_eq!("1403/05/27", crate::jalali_date_trunc_week("1403/05/28"));
+        assert_eq!("1403/05/27", crate::jalali_date_trunc_week("1403/05/27"));
+        assert_eq!("1403/05/27", crate::jalali_date_trunc_week("1403/06/02"));
+        assert_eq!("1402/12/26", crate::jalali_date_trunc_week("1403/01/01"));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_format() {
+        assert_eq!(
+            "1403-05-28",
+            crate::jalali_date_format("1403/5/28", "%Y-%m-%d")
+        );
+        assert_eq!(
+            "یکشنبه 28 مرداد 1403",
+            crate::jalali_date_format("1403/05/28", "%A %d %B %Y")
+        );
+        assert_eq!(
+            "سال 1403 روز 152",
+            crate::jalali_date_format("1403/05/28", "'سال' %Y 'روز' %j")
+        );
+        assert_eq!(
+            "%Y 1403 it's",
+            crate::jalali_date_format("1403/05/28", "'%Y' %Y 'it''s'")
+        );
+        assert_eq!("'05'", crate::jalali_date_format("1403/05/28", "''%m''"));
+    }
+
+    #[pg_test(error = "invalid pattern %Q specifier %Q")]
+    fn test_jalali_date_format_unknown_specifier() {
+        crate::jalali_date_format("1403/05/28", "%Q");
+    }
+
+    #[pg_test(error = "invalid pattern 'abc unterminated quote")]
+    fn test_jalali_date_format_unterminated_quote() {
+        crate::jalali_date_format("1403/05/28", "'abc");
+    }
+
+    #[pg_test]
+    fn test_any_date_to_jalali() {
+        assert_eq!("1403/05/28", crate::any_date_to_jalali("1403/5/28"));
+        assert_eq!("1403/05/28", crate::any_date_to_jalali("2024-08-18"));
+    }
+
+    #[pg_test(error = "invalid date 2024/08/18 ambiguous calendar")]
+    fn test_any_date_to_jalali_ambiguous() {
+        crate::any_date_to_jalali("2024/08/18");
+    }
+
+    #[pg_test]
+    fn test_jalali_days_to_anniversary() {
+        assert_eq!(
+            3,
+            crate::jalali_days_to_anniversary("1370/05/31", "1403/05/28")
+        );
+        assert_eq!(
+            0,
+            crate::jalali_days_to_anniversary("1370/05/28", "1403/05/28")
+        );
+        assert_eq!(
+            365,
+            crate::jalali_days_to_anniversary("1370/05/27", "1403/05/28")
+        );
+        assert_eq!(
+            1,
+            crate::jalali_days_to_anniversary("1399/12/30", "1404/12/28")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_datetime_in_business_hours() {
+        assert!(crate::jalali_datetime_in_business_hours(
+            "1403/05/28 10:30:00",
+            8,
+            17
+        ));
+        assert!(crate::jalali_datetime_in_business_hours(
+            "1403/05/28 08:00",
+            8,
+            17
+        ));
+        assert!(!crate::jalali_datetime_in_business_hours(
+            "1403/05/28 17:00:00",
+            8,
+            17
+        ));
+        assert!(!crate::jalali_datetime_in_business_hours(
+            "1403/05/28 07:59:59",
+            8,
+            17
+        ));
+        assert!(!crate::jalali_datetime_in_business_hours(
+            "1403/06/02 10:30:00",
+            8,
+            17
+        ));
+    }
+
+    #[pg_test(error = "invalid business hours 17 8")]
+    fn test_jalali_datetime_in_business_hours_invalid_hours() {
+        crate::jalali_datetime_in_business_hours("1403/05/28 10:30:00", 17, 8);
+    }
+
+    #[pg_test]
+    fn test_jalali_working_minutes() {
+        assert_eq!(
+            150,
+            crate::jalali_working_minutes("1403/05/28 10:30", "1403/05/28 13:00", 8, 17, vec![])
+        );
+        assert_eq!(
+            60,
+            crate::jalali_working_minutes("1403/05/28 06:00", "1403/05/28 09:00", 8, 17, vec![])
+        );
+        // Thursday 16:00 to Saturday 09:00 skips Friday entirely.
+        assert_eq!(
+            120,
+            crate::jalali_working_minutes("1403/06/01 16:00", "1403/06/03 09:00", 8, 17, vec![])
+        );
+        assert_eq!(
+            -120,
+            crate::jalali_working_minutes("1403/06/03 09:00", "1403/06/01 16:00", 8, 17, vec![])
+        );
+        assert_eq!(
+            60,
+            crate::jalali_working_minutes(
+                "1403/06/01 16:00",
+                "1403/06/03 09:00",
+                8,
+                17,
+                vec!["1403/06/01".to_string()]
+            )
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_to_gregorian_agg() -> Result<(), spi::Error> {
+        let result = Spi::get_one::<Vec<String>>(
+            "SELECT jalali_to_gregorian_agg(d) FROM (VALUES ('1403/05/28'), (NULL), ('1403/13/01'), ('1403/01/01')) AS t(d)",
+        )?;
+        assert_eq!(
+            Some(vec!["2024-08-18".to_string(), "2024-03-20".to_string()]),
+            result
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    fn test_jalali_month_bucket() {
+        let bucket = crate::jalali_month_bucket("1403/05/28");
+        assert_eq!(1403 * 12 + 5, bucket);
+        assert_eq!("1403/05", crate::jalali_month_bucket_to_label(bucket));
+        assert_eq!(
+            "1403/12",
+            crate::jalali_month_bucket_to_label(crate::jalali_month_bucket("1403/12/30"))
+        );
+        assert_eq!(
+            1,
+            crate::jalali_month_bucket("1404/01/01") - crate::jalali_month_bucket("1403/12/30")
+        );
+        assert_eq!(
+            1,
+            crate::jalali_month_bucket("1403/06/01") - crate::jalali_month_bucket("1403/05/31")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_datetime_parse_t_separator() {
+        assert_eq!(
+            crate::jalali_datetime_parse("1403/05/28 14:30:00"),
+            crate::jalali_datetime_parse("1403/05/28T14:30:00")
+        );
+        assert!(crate::jalali_datetime_in_business_hours(
+            "1403/05/28T14:30:00",
+            8,
+            17
+        ));
+    }
+
+    #[pg_test]
+    fn test_jalali_weeks_in_year() {
+        assert_eq!(52, crate::jalali_weeks_in_year(1403));
+        assert_eq!(53, crate::jalali_weeks_in_year(1402));
+        assert_eq!(53, crate::jalali_weeks_in_year(1408));
+    }
+
+    #[pg_test]
+    fn test_jalali_start_end_of_day() {
+        assert_eq!(
+            "1403/05/28 00:00:00",
+            crate::jalali_start_of_day("1403/5/28")
+        );
+        assert_eq!(
+            "1403/05/28 23:59:59",
+            crate::jalali_end_of_day("1403/05/28")
+        );
+    }
+
+    #[pg_test(error = "invalid date 1402/12/30 jalali date")]
+    fn test_jalali_start_of_day_invalid() {
+        crate::jalali_start_of_day("1402/12/30");
+    }
+
+    #[pg_test]
+    fn test_jalali_closest() {
+        assert_eq!(
+            Some("1403/05/30".to_string()),
+            crate::jalali_closest(
+                "1403/05/28",
+                vec![
+                    "1403/05/20".to_string(),
+                    "1403/05/30".to_string(),
+                    "1403/06/01".to_string(),
+                    "invalid".to_string(),
+                ]
+            )
+        );
+        assert_eq!(
+            Some("1403/05/26".to_string()),
+            crate::jalali_closest(
+                "1403/05/28",
+                vec!["1403/05/30".to_string(), "1403/05/26".to_string()]
+            )
+        );
+        assert_eq!(None, crate::jalali_closest("1403/05/28", vec![]));
+    }
+
+    #[pg_test]
+    fn test_jalali_farthest() {
+        assert_eq!(
+            Some("1403/03/01".to_string()),
+            crate::jalali_farthest(
+                "1403/05/28",
+                vec![
+                    "1403/05/20".to_string(),
+                    "1403/03/01".to_string(),
+                    "1403/07/01".to_string(),
+                    "1403/13/01".to_string(),
+                ]
+            )
+        );
+        assert_eq!(None, crate::jalali_farthest("1403/05/28", vec![]));
+    }
+
+    #[pg_test]
+    fn test_jalali_mode() -> Result<(), spi::Error> {
+        let result = Spi::get_one::<String>(
+            "SELECT jalali_mode(d) FROM (VALUES ('1403/05/28'), ('1403/01/01'), ('1403/5/28'), ('1403/01/01'), ('1403/05/28'), ('bad')) AS t(d)",
+        )?;
+        assert_eq!(Some("1403/05/28".to_string()), result);
+
+        let result = Spi::get_one::<String>(
+            "SELECT jalali_mode(d) FROM (VALUES ('1403/05/28'), ('1403/01/01')) AS t(d)",
+        )?;
+        assert_eq!(Some("1403/01/01".to_string()), result);
+
+        let result = Spi::get_one::<String>(
+            "SELECT jalali_mode(d) FROM (VALUES ('1000000/01/01'), ('1000000/01/01'), ('1403/01/01')) AS t(d)",
+        )?;
+        assert_eq!(Some("1000000/01/01".to_string()), result);
+        Ok(())
+    }
+
+    #[pg_test]
+    fn test_jalali_date_from_day_number() {
+        for date in [
+            "1403/12/30",
+            "0001/01/01",
+            "-5/12/29",
+            "0/01/01",
+            "1000000/07/15",
+        ] {
+            let date_value = crate::jalali_date_parse(date);
+            assert_eq!(
+                Some(date_value),
+                crate::jalali_date_from_day_number(crate::jalali_day_number(&date_value))
+            );
+        }
+        assert_eq!(
+            Some(crate::jalali_date_parse("1348/10/11")),
+            crate::jalali_date_from_day_number(719163)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_percentile() -> Result<(), spi::Error> {
+        let result = Spi::get_one::<String>(
+            "SELECT jalali_percentile(0.5) WITHIN GROUP (ORDER BY d) FROM (VALUES ('1403/05/28'), ('1402/12/29'), ('1403/1/1')) AS t(d)",
+        )?;
+        assert_eq!(Some("1403/01/01".to_string()), result);
+
+        let result = Spi::get_one::<String>(
+            "SELECT jalali_percentile(0.5) WITHIN GROUP (ORDER BY d) FROM (VALUES ('1403/05/28'), ('1402/12/29'), ('1403/01/01'), ('1403/10/01')) AS t(d)",
+        )?;
+        assert_eq!(Some("1403/01/01".to_string()), result);
+
+        let result = Spi::get_one::<String>(
+            "SELECT jalali_percentile(1.0) WITHIN GROUP (ORDER BY d) FROM (VALUES ('1403/05/28'), ('1000000/01/01')) AS t(d)",
+        )?;
+        assert_eq!(Some("1000000/01/01".to_string()), result);
+        Ok(())
+    }
+
+    #[pg_test]
+    fn test_jalali_normalize_array() {
+        assert_eq!(
+            vec![
+                Some("1403/05/28".to_string()),
+                Some("1403/05/08".to_string()),
+                Some("1403/01/01".to_string()),
+                Some("1403/12/30".to_string()),
+                Some("-0005/01/01".to_string()),
+                None,
+                None,
+            ],
+            crate::jalali_normalize_array(vec![
+                "1403-05-28".to_string(),
+                "1403/5/8".to_string(),
+                " 1403/01/01 ".to_string(),
+                "1403-12-30".to_string(),
+                "-5/01/01".to_string(),
+                "1402/12/30".to_string(),
+                "not a date".to_string(),
+            ])
+        );
+        assert_eq!(
+            vec![
+                Some("1403/05/28".to_string()),
+                Some("1403/05/08".to_string()),
+                Some("-0005/01/01".to_string()),
+                None,
+            ],
+            crate::jalali_normalize_array(vec![
+                "۱۴۰۳/۰۵/۲۸".to_string(),
+                "١٤٠٣-5-۸".to_string(),
+                "-۵-۱-۱".to_string(),
+                "1403.12.30".to_string(),
+            ])
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_assert_weekday() {
+        assert!(crate::jalali_assert_weekday("1403/06/02", "جمعه"));
+        assert!(crate::jalali_assert_weekday("1403/06/02", "Friday"));
+        assert!(crate::jalali_assert_weekday("1403/05/30", "سه شنبه"));
+        assert!(crate::jalali_assert_weekday("1403/05/30", "سه‌شنبه"));
+        assert!(!crate::jalali_assert_weekday("1403/05/28", "جمعه"));
+    }
+
+    #[pg_test(error = "invalid weekday Funday")]
+    fn test_jalali_assert_weekday_invalid_weekday() {
+        crate::jalali_assert_weekday("1403/05/28", "Funday");
+    }
+
+    #[pg_test]
+    fn test_hijri_holiday_in_jalali() {
+        assert_eq!(
+            vec!["1403/04/27".to_string()],
+            crate::hijri_holiday_in_jalali(1, 10, 1403)
+        );
+        assert_eq!(
+            vec!["1403/01/06".to_string(), "1403/12/25".to_string()],
+            crate::hijri_holiday_in_jalali(9, 15, 1403)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_diff_ymd() {
+        assert_eq!(
+            vec![(3, 0, 0)],
+            crate::jalali_diff_ymd("1400/01/01", "1403/01/01").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(0, 0, 14)],
+            crate::jalali_diff_ymd("1402/12/20", "1403/01/05").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(0, 0, 15)],
+            crate::jalali_diff_ymd("1403/12/20", "1404/01/05").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(1, 2, 3)],
+            crate::jalali_diff_ymd("1402/11/25", "1404/01/28").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(-1, -2, -3)],
+            crate::jalali_diff_ymd("1404/01/28", "1402/11/25").collect::<Vec<_>>()
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_dmy() {
+        assert_eq!("1403/06/05", crate::jalali_parse_dmy("05/06/1403"));
+        assert_eq!("1403/05/28", crate::jalali_parse_dmy("28/5/1403"));
+        assert_eq!("1403/05/28", crate::jalali_parse_dmy("۲۸-۰۵-۱۴۰۳"));
+        assert!(crate::jalali_date_try_parse("05/06/1403").is_err());
+    }
+
+    #[pg_test(error = "invalid date 28/xx/1403 month value")]
+    fn test_jalali_parse_dmy_non_numeric() {
+        crate::jalali_parse_dmy("28/xx/1403");
+    }
+
+    #[pg_test(error = "invalid date 30/12/1402 day-first jalali date")]
+    fn test_jalali_parse_dmy_invalid() {
+        crate::jalali_parse_dmy("30/12/1402");
+    }
+
+    #[pg_test]
+    fn test_jalali_next_day_of_month() {
+        assert_eq!(
+            "1403/05/15",
+            crate::jalali_next_day_of_month("1403/05/10", 15)
+        );
+        assert_eq!(
+            "1403/05/15",
+            crate::jalali_next_day_of_month("1403/05/15", 15)
+        );
+        assert_eq!(
+            "1403/06/15",
+            crate::jalali_next_day_of_month("1403/05/28", 15)
+        );
+        assert_eq!(
+            "1404/01/15",
+            crate::jalali_next_day_of_month("1403/12/20", 15)
+        );
+        assert_eq!(
+            "1403/07/30",
+            crate::jalali_next_day_of_month("1403/07/10", 31)
+        );
+        assert_eq!(
+            "1402/12/29",
+            crate::jalali_next_day_of_month("1402/12/01", 31)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_business_calendar() {
+        let rows: Vec<_> = crate::jalali_business_calendar(
+            "1403/06/01",
+            "1403/06/04",
+            vec!["1403/06/03".to_string()],
+        )
+        .collect();
+        assert_eq!(
+            vec![
+                ("1403/06/01".to_string(), true, "پنجشنبه".to_string()),
+                ("1403/06/02".to_string(), false, "جمعه".to_string()),
+                ("1403/06/03".to_string(), false, "شنبه".to_string()),
+                ("1403/06/04".to_string(), true, "یکشنبه".to_string()),
+            ],
+            rows
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_month_name_dari() {
+        assert_eq!("حمل", crate::jalali_month_name_dari("1403/01/01"));
+        assert_eq!("حوت", crate::jalali_month_name_dari("1403/12/30"));
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_written() {
+        assert_eq!("1403/05/28", crate::jalali_parse_written("28 مرداد 1403"));
+        assert_eq!("1403/05/28", crate::jalali_parse_written("28 اسد 1403"));
+        assert_eq!("1403/01/01", crate::jalali_parse_written("1 حمل 1403"));
+        assert_eq!("1403/12/30", crate::jalali_parse_written("30 حوت 1403"));
+    }
+
+    #[pg_test(error = "invalid date 28 August 1403 month name")]
+    fn test_jalali_parse_written_unknown_month() {
+        crate::jalali_parse_written("28 August 1403");
+    }
+
+    #[pg_test]
+    fn test_jalali_tz_offset() {
+        assert_eq!(
+            "+04:30",
+            crate::jalali_tz_offset("1400/05/01", "Asia/Tehran")
+        );
+        assert_eq!(
+            "+03:30",
+            crate::jalali_tz_offset("1400/10/01", "Asia/Tehran")
+        );
+        assert_eq!(
+            "+03:30",
+            crate::jalali_tz_offset("1403/05/28", "Asia/Tehran")
+        );
+        assert_eq!("+00:00", crate::jalali_tz_offset("1403/05/28", "UTC"));
+    }
+
+    #[pg_test(error = "invalid time zone Mars/Olympus")]
+    fn test_jalali_tz_offset_invalid_tz() {
+        crate::jalali_tz_offset("1403/05/28", "Mars/Olympus");
+    }
+
+    #[pg_test]
+    fn test_jalali_date_from_week() {
+        assert_eq!(
+            "1403/01/08",
+            crate::jalali_date_from_week(1403, 1, "چهارشنبه")
+        );
+        assert_eq!("1403/01/11", crate::jalali_date_from_week(1403, 2, "شنبه"));
+        assert_eq!("1402/12/26", crate::jalali_date_from_week(1402, 53, "شنبه"));
+        for date in [
+            "1403/05/28",
+            "1403/01/01",
+            "1403/12/30",
+            "1401/12/29",
+            "1402/01/01",
+        ] {
+            let date_value = crate::jalali_date_parse(date);
+            let weekday = crate::JALALI_WEEKDAY_NAMES[crate::jalali_weekday_index(&date_value)];
+            let week = crate::jalali_week_of_year(date);
+            let round_trip =
+                crate::jalali_date_from_week(crate::jalali_week_year(date), week, weekday);
+            assert_eq!(date, round_trip);
+            assert!(crate::jalali_assert_weekday(&round_trip, weekday));
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_week_of_year() {
+        assert_eq!(53, crate::jalali_week_of_year("1403/01/01"));
+        assert_eq!(1, crate::jalali_week_of_year("1403/01/04"));
+        assert_eq!(52, crate::jalali_week_of_year("1403/12/29"));
+        assert_eq!(1, crate::jalali_week_of_year("1401/12/29"));
+        for year in 1395..1415 {
+            let last_day = crate::jalali_date_to_string(&crate::jalali_date_clamped(year, 12, 30));
+            let first_day = format!("{year}/01/01");
+            assert!(crate::jalali_week_of_year(&last_day) <= crate::jalali_weeks_in_year(year));
+            assert!(
+                crate::jalali_week_of_year(&first_day) <= crate::jalali_weeks_in_year(year - 1)
+            );
+            assert_eq!(
+                crate::jalali_weeks_in_year(year),
+                crate::jalali_week_of_year(&crate::jalali_date_from_week(
+                    year,
+                    crate::jalali_weeks_in_year(year),
+                    crate::JALALI_WEEKDAY_NAMES[3]
+                ))
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_week_year() {
+        assert_eq!(1402, crate::jalali_week_year("1403/01/01"));
+        assert_eq!(1403, crate::jalali_week_year("1403/01/04"));
+        assert_eq!(1403, crate::jalali_week_year("1403/12/30"));
+        assert_eq!(1402, crate::jalali_week_year("1401/12/29"));
+        assert_eq!(1401, crate::jalali_week_year("1401/12/25"));
+    }
+
+    #[pg_test(error = "invalid week 53 for year 1403")]
+    fn test_jalali_date_from_week_out_of_range() {
+        crate::jalali_date_from_week(1403, 53, "شنبه");
+    }
+
+    #[pg_test]
+    fn test_jalali_date_diff_abs() {
+        assert_eq!(38, crate::jalali_date_diff_abs("1403/05/28", "1403/07/04"));
+        assert_eq!(38, crate::jalali_date_diff_abs("1403/07/04", "1403/05/28"));
+        assert_eq!(0, crate::jalali_date_diff_abs("1403/05/28", "1403/05/28"));
+    }
+
+    #[pg_test]
+    fn test_jalali_to_gregorian_cutoff() {
+        let after = crate::gregorian_date_to_jalali("1582-10-15");
+        let before = crate::jalali_date_add_days(&after, -1);
+        assert_eq!(
+            "1582-10-15",
+            crate::jalali_to_gregorian_cutoff(&after, "1582-10-15")
+        );
+        assert_eq!(
+            "1582-10-04",
+            crate::jalali_to_gregorian_cutoff(&before, "1582-10-15")
+        );
+        assert_eq!(
+            "2024-08-18",
+            crate::jalali_to_gregorian_cutoff("1403/05/28", "1582-10-15")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_round_trip_ok() {
+        assert!(crate::jalali_round_trip_ok("1403/05/28"));
+        assert!(crate::jalali_round_trip_ok("1403/12/30"));
+        assert!(crate::jalali_round_trip_ok("300/01/01"));
+        assert!(crate::jalali_round_trip_ok("-700/01/01"));
+        assert!(!crate::jalali_round_trip_ok("1402/12/30"));
+    }
+
+    #[pg_test]
+    fn test_gregorian_date_to_jalali_negative_year() {
+        assert_eq!(
+            "-0700/01/01",
+            crate::gregorian_date_to_jalali("-0079-03-21")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_add_days_or_null() {
+        assert_eq!(
+            Some("1403/06/02".to_string()),
+            crate::jalali_add_days_or_null("1403/05/28", 5)
+        );
+        assert_eq!(None, crate::jalali_add_days_or_null("1403/13/01", 5));
+        assert_eq!(None, crate::jalali_add_days_or_null("1403/05/28", i32::MAX));
+        assert_eq!(None, crate::jalali_add_days_or_null("1403/05/28", i32::MIN));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_to_words() {
+        assert_eq!(
+            "بیست و هشتم مرداد یک هزار و چهارصد و سه",
+            crate::jalali_date_to_words("1403/05/28")
+        );
+        assert_eq!(
+            "یکم فروردین یک هزار و چهارصد",
+            crate::jalali_date_to_words("1400/01/01")
+        );
+        assert_eq!(
+            "سی‌ام اسفند یک هزار و سیصد و نود و نه",
+            crate::jalali_date_to_words("1399/12/30")
+        );
+        assert_eq!(
+            "سیزدهم تیر دو هزار و یازده",
+            crate::jalali_date_to_words("2011/04/13")
+        );
+        assert_eq!(
+            "بیست و سوم دی یکصد و بیست",
+            crate::jalali_date_to_words("120/10/23")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_today_in_words() {
+        let words = crate::jalali_today_in_words("Asia/Tehran");
+        assert!(crate::JALALI_MONTH_NAMES.iter().any(|x| words.contains(x)));
+        assert_eq!(
+            crate::jalali_date_to_words(&crate::jalali_date_now_tz("Asia/Tehran")),
+            words
+        );
+    }
+
+    #[pg_test]
+    fn test_gregorian_is_leap_for_jalali() {
+        assert!(crate::gregorian_is_leap_for_jalali("1403/01/01"));
+        assert!(!crate::gregorian_is_leap_for_jalali("1402/01/01"));
+        assert!(!crate::gregorian_is_leap_for_jalali("1403/10/12"));
+    }
+
+    #[pg_test]
+    fn test_jalali_interval_has_weekday() {
+        assert!(crate::jalali_interval_has_weekday(
+            "1403/06/01",
+            "1403/06/07",
+            "شنبه"
+        ));
+        assert!(!crate::jalali_interval_has_weekday(
+            "1403/06/01",
+            "1403/06/02",
+            "شنبه"
+        ));
+        assert!(crate::jalali_interval_has_weekday(
+            "1403/06/02",
+            "1403/06/01",
+            "جمعه"
+        ));
+    }
+
+    #[pg_test]
+    fn test_jalali_shift_weeks_preserving_weekday() {
+        assert_eq!(
+            "1403/06/29",
+            crate::jalali_shift_weeks_preserving_weekday("1403/06/01", 4)
+        );
+        for weeks in [-60, -3, -1, 0, 1, 5, 60] {
+            let shifted = crate::jalali_shift_weeks_preserving_weekday("1403/05/28", weeks);
+            assert_eq!(
+                crate::jalali_weekday_index(&crate::jalali_date_parse("1403/05/28")),
+                crate::jalali_weekday_index(&crate::jalali_date_parse(&shifted))
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_fixed_holidays_in_year() {
+        let rows: Vec<(String, String)> = crate::jalali_fixed_holidays_in_year(1403).collect();
+        assert_eq!(10, rows.len());
+        let nowruz: Vec<&str> = rows
+            .iter()
+            .filter(|(_, name)| name == "عید نوروز")
+            .map(|(date, _)| date.as_str())
+            .collect();
+        assert_eq!(
+            vec!["1403/01/01", "1403/01/02", "1403/01/03", "1403/01/04"],
+            nowruz
+        );
+        assert_eq!(
+            ("1403/11/22".to_string(), "پیروزی انقلاب اسلامی".to_string()),
+            rows[8]
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_with_era() {
+        assert_eq!("1403/05/28", crate::jalali_parse_with_era("1403/05/28"));
+        assert_eq!(
+            "1403/05/28",
+            crate::jalali_parse_with_era("1403/05/28 ه\u{200d}.ش")
+        );
+        assert_eq!("1403/05/28", crate::jalali_parse_with_era("1403/5/28 هـ.ش"));
+        assert_eq!(
+            "1403/05/28",
+            crate::jalali_parse_with_era(" 1403/05/28 AP ")
+        );
+    }
+
+    #[pg_test(error = "invalid date 1403/05/28 AD era marker AD")]
+    fn test_jalali_parse_with_era_unknown() {
+        crate::jalali_parse_with_era("1403/05/28 AD");
+    }
+
+    #[pg_test]
+    fn test_jalali_date_add_months() {
+        assert_eq!("1403/04/15", crate::jalali_date_add_months("1403/01/15", 3));
+        assert_eq!(
+            "1402/10/15",
+            crate::jalali_date_add_months("1403/01/15", -3)
+        );
+        assert_eq!("1403/05/28", crate::jalali_date_add_months("1403/05/28", 0));
+        assert_eq!("1403/07/30", crate::jalali_date_add_months("1403/06/31", 1));
+        assert_eq!(
+            "1403/11/30",
+            crate::jalali_date_add_months("1404/01/31", -2)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_date_add_months_esfand() {
+        assert_eq!(
+            "1403/12/30",
+            crate::jalali_date_add_months("1404/01/30", -1)
+        );
+        assert_eq!(
+            "1402/12/29",
+            crate::jalali_date_add_months("1403/01/31", -1)
+        );
+        assert_eq!(
+            "1403/12/29",
+            crate::jalali_date_add_months("1405/12/29", -24)
+        );
+        assert_eq!(
+            "1399/12/30",
+            crate::jalali_date_add_months("1403/12/30", -48)
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_datetime_diff_seconds() {
+        assert_eq!(
+            5430,
+            crate::jalali_datetime_diff_seconds("1403/05/28 08:00:00", "1403/05/28 09:30:30")
+        );
+        assert_eq!(
+            -5430,
+            crate::jalali_datetime_diff_seconds("1403/05/28 09:30:30", "1403/05/28 08:00:00")
+        );
+        assert_eq!(
+            4 * 86400 - 3600,
+            crate::jalali_datetime_diff_seconds("1403/06/30 12:00:00", "1403/07/03 11:00:00")
+        );
+    }
+
+    /// Runs `query` in a subtransaction and returns the SQLSTATE it failed with, if any.
+    fn sql_error_code(query: &str) -> Option<PgSqlErrorCode> {
+        let old_context = unsafe { pg_sys::CurrentMemoryContext };
+        let old_owner = unsafe { pg_sys::CurrentResourceOwner };
+        unsafe { pg_sys::BeginInternalSubTransaction(std::ptr::null()) };
+        let result = PgTryBuilder::new(|| {
+            let result = Spi::run(query);
+            unsafe { pg_sys::ReleaseCurrentSubTransaction() };
+            result.map(|_| None)
+        })
+        .catch_others(|e| {
+            unsafe { pg_sys::RollbackAndReleaseCurrentSubTransaction() };
+            match e {
+                pg_sys::panic::CaughtError::PostgresError(x)
+                | pg_sys::panic::CaughtError::ErrorReport(x) => Ok(Some(x.sql_error_code())),
+                e => e.rethrow(),
+            }
+        })
+        .execute();
+        unsafe {
+            pg_sys::MemoryContextSwitchTo(old_context);
+            pg_sys::CurrentResourceOwner = old_owner;
+        }
+        match result {
+            Ok(x) => x,
+            Err(e) => panic!("{query} failed without raising an error: {e}"),
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_date_to_gregorian_sql_error() {
+        assert_eq!(
+            Some(PgSqlErrorCode::ERRCODE_INVALID_DATETIME_FORMAT),
+            sql_error_code("SELECT jalali_date_to_gregorian('not/a/date')")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_to_gregorian_fmt() {
+        assert_eq!(
+            "18/08/2024",
+            crate::jalali_to_gregorian_fmt("1403/05/28", "%d/%m/%Y")
+        );
+        assert_eq!(
+            "August 18, 2024",
+            crate::jalali_to_gregorian_fmt("1403/05/28", "%B %d, %Y")
+        );
+    }
+
+    #[pg_test(error = "invalid pattern %Q")]
+    fn test_jalali_to_gregorian_fmt_invalid() {
+        crate::jalali_to_gregorian_fmt("1403/05/28", "%Q");
+    }
+
+    #[pg_test(error = "invalid pattern %H:%M for date")]
+    fn test_jalali_to_gregorian_fmt_time_specifier() {
+        crate::jalali_to_gregorian_fmt("1403/05/28", "%H:%M");
+    }
+
+    #[pg_test]
+    fn test_jalali_date_weekday() {
+        assert_eq!("سه‌شنبه", crate::jalali_date_weekday("1402/01/01"));
+        assert_eq!(3, crate::jalali_date_weekday_num("1402/01/01"));
+        assert_eq!("چهارشنبه", crate::jalali_date_weekday("1403/01/01"));
+        assert_eq!(4, crate::jalali_date_weekday_num("1403/01/01"));
+        assert_eq!("شنبه", crate::jalali_date_weekday("1403/06/03"));
+        assert_eq!(0, crate::jalali_date_weekday_num("1403/06/03"));
+        assert_eq!("جمعه", crate::jalali_date_weekday("1403/06/02"));
+        assert_eq!(6, crate::jalali_date_weekday_num("1403/06/02"));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_format_tokens() {
+        assert_eq!(
+            "چهارشنبه 01 فروردین 1403",
+            crate::jalali_date_format("1403/01/01", "dddd DD MMMM YYYY")
+        );
+        assert_eq!(
+            "1403/05/28",
+            crate::jalali_date_format("1403/5/28", "YYYY/MM/DD")
+        );
+        assert_eq!(
+            "YYYY 1403",
+            crate::jalali_date_format("1403/05/28", "'YYYY' YYYY")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_datetime_representable() {
+        assert!(crate::jalali_datetime_representable("1403/05/28 14:30:00"));
+        assert!(crate::jalali_datetime_representable("293655/01/01 00:00"));
+        assert!(!crate::jalali_datetime_representable(
+            "300000/01/01 00:00:00"
+        ));
+        assert!(!crate::jalali_datetime_representable(
+            "-6000/01/01 00:00:00"
+        ));
+        assert!(!crate::jalali_datetime_representable("1403/05/28"));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_diff_detailed() {
+        assert_eq!(
+            vec![(3, 0, 0, 1095, false)],
+            crate::jalali_date_diff_detailed("1400/01/01", "1403/01/01").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(0, 1, 0, 31, false)],
+            crate::jalali_date_diff_detailed("1403/06/31", "1403/08/01").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(-1, 0, 0, -366, true)],
+            crate::jalali_date_diff_detailed("1404/01/01", "1403/01/01").collect::<Vec<_>>()
+        );
+        for (start, end) in [("1403/08/01", "1403/06/31"), ("1403/05/28", "1400/02/03")] {
+            let (years, months, days, total_days, invert) =
+                crate::jalali_date_diff_detailed(start, end).next().unwrap();
+            assert_eq!(
+                vec![(years, months, days)],
+                crate::jalali_diff_ymd(start, end).collect::<Vec<_>>()
+            );
+            assert!(years <= 0 && months <= 0 && days <= 0);
+            assert!(invert && total_days < 0);
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_to_date() {
+        assert_eq!(
+            pgrx::datum::Date::new(2024, 3, 20).unwrap(),
+            crate::jalali_to_date("1403/01/01")
+        );
+        for date in ["1403/01/01", "1403/12/30", "0001/01/01", "-0700/06/15"] {
+            assert_eq!(
+                crate::jalali_date_to_string(&crate::jalali_date_parse(date)),
+                crate::gregorian_to_jalali(crate::jalali_to_date(date))
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_to_date_sql_ordering() -> Result<(), spi::Error> {
+        assert_eq!(
+            Some(true),
+            Spi::get_one::<bool>(
+                "SELECT jalali_to_date('1403/01/01') = '2024-03-20'::date \
+                 AND jalali_to_date('1402/12/29') < '2024-03-20'::date",
+            )?
+        );
+        Ok(())
+    }
+
+    #[pg_test(error = "invalid date 5874898/01/01 out of range")]
+    fn test_jalali_to_date_out_of_range() {
+        crate::jalali_to_date("5874898/01/01");
+    }
+
+    #[pg_test]
+    fn test_jalali_to_date_range_bounds() {
+        for date in ["-5334/09/03", "5874278/01/01"] {
+            assert_eq!(
+                date,
+                crate::gregorian_to_jalali(crate::jalali_to_date(date))
+            );
+        }
+    }
+
+    #[pg_test(error = "invalid date -5334/09/02 out of range")]
+    fn test_jalali_to_date_before_range() {
+        crate::jalali_to_date("-5334/09/02");
+    }
+
+    #[pg_test(error = "invalid date 5874278/01/02 out of range")]
+    fn test_jalali_to_date_after_range() {
+        crate::jalali_to_date("5874278/01/02");
+    }
+
+    #[pg_test]
+    fn test_jalali_weekday_offset() {
+        assert_eq!(0, crate::jalali_weekday_offset("1403/06/01", "پنجشنبه"));
+        assert_eq!(-2, crate::jalali_weekday_offset("1403/06/01", "سه‌شنبه"));
+        assert_eq!(1, crate::jalali_weekday_offset("1403/06/01", "جمعه"));
+        assert_eq!(-5, crate::jalali_weekday_offset("1403/06/01", "شنبه"));
+        assert_eq!(-3, crate::jalali_weekday_offset("1403/06/01", "Monday"));
+        assert_eq!(-6, crate::jalali_weekday_offset("1403/06/02", "شنبه"));
+        assert_eq!(6, crate::jalali_weekday_offset("1403/05/27", "جمعه"));
+        for date in ["1403/06/02", "1403/05/27", "1403/06/01"] {
+            let start = crate::jalali_weekday_offset(date, "شنبه");
+            assert_eq!(
+                crate::jalali_date_trunc_week(date),
+                crate::jalali_date_add_days(date, start)
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_generate_jalali_series() {
+        let series: Vec<String> =
+            crate::generate_jalali_series("1403/06/30", "1403/07/02", 1).collect();
+        assert_eq!(
+            vec!["1403/06/30", "1403/06/31", "1403/07/01", "1403/07/02"],
+            series
+        );
+        assert_eq!(
+            31,
+            crate::generate_jalali_series("1403/01/01", "1403/01/31", 1).count()
+        );
+        assert_eq!(
+            3,
+            crate::generate_jalali_series("1403/12/28", "1404/01/02", 2).count()
+        );
+        assert_eq!(
+            3,
+            crate::generate_jalali_series("1402/12/28", "1403/01/01", 1).count()
+        );
+        assert_eq!(
+            vec![
+                "1000000/01/01",
+                "1000000/01/04",
+                "1000000/01/07",
+                "1000000/01/10"
+            ],
+            crate::generate_jalali_series("1000000/01/01", "1000000/01/10", 3).collect::<Vec<_>>()
+        );
+    }
+
+    #[pg_test]
+    fn test_generate_jalali_series_backwards() {
+        let series: Vec<String> =
+            crate::generate_jalali_series("1404/01/01", "1403/12/29", -1).collect();
+        assert_eq!(vec!["1404/01/01", "1403/12/30", "1403/12/29"], series);
+        assert_eq!(
+            0,
+            crate::generate_jalali_series("1403/01/01", "1403/01/31", -1).count()
+        );
+    }
+
+    #[pg_test(error = "invalid step 0")]
+    fn test_generate_jalali_series_zero_step() {
+        crate::generate_jalali_series("1403/01/01", "1403/01/31", 0);
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_any() {
+        let formats = vec!["%Y/%m/%d".to_string(), "%d-%m-%Y".to_string()];
+        assert_eq!(
+            Some("1403/05/28".to_string()),
+            crate::jalali_parse_any("28-05-1403", formats.clone())
+        );
+        assert_eq!(
+            Some("1403/05/08".to_string()),
+            crate::jalali_parse_any("1403/5/8", formats.clone())
+        );
+        assert_eq!(None, crate::jalali_parse_any("1403.05.28", formats.clone()));
+        assert_eq!(None, crate::jalali_parse_any("30-12-1402", formats));
+        assert_eq!(
+            Some("1403/05/28".to_string()),
+            crate::jalali_parse_any("28 مرداد 1403", vec!["%d %B %Y".to_string()])
+        );
+    }
+
+    #[pg_test]
+    fn test_invalid_date_sqlstate() {
+        for query in [
+            "SELECT jalali_date_add_days('1403/13/01', 1)",
+            "SELECT jalali_date_diff('1403/01/01', '1402/12/30')",
+            "SELECT gregorian_date_to_jalali('2024-02-30')",
+            "SELECT jalali_date_format('1403/05', '%Y')",
+        ] {
+            assert_eq!(
+                Some(PgSqlErrorCode::ERRCODE_INVALID_DATETIME_FORMAT),
+                sql_error_code(query)
+            );
+        }
+        assert_eq!(
+            None,
+            sql_error_code("SELECT jalali_date_add_days('1403/12/30', 1)")
+        );
+    }
+
+    #[pg_test]
+    fn test_invalid_parameter_sqlstate() {
+        for query in [
+            "SELECT jalali_tz_offset('1403/05/28', 'Mars/Olympus')",
+            "SELECT jalali_date_from_week(1403, 1, 'caturday')",
+            "SELECT hijri_holiday_in_jalali(13, 1, 1403)",
+            "SELECT jalali_month_weekday_counts(1403, 13)",
+        ] {
+            assert_eq!(
+                Some(PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE),
+                sql_error_code(query)
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_date_start_end_of_month() {
+        assert_eq!(
+            "1403/05/01",
+            crate::jalali_date_start_of_month("1403/05/28")
+        );
+        assert_eq!("1403/05/31", crate::jalali_date_end_of_month("1403/05/28"));
+        assert_eq!("1403/08/30", crate::jalali_date_end_of_month("1403/08/01"));
+        assert_eq!("1403/12/30", crate::jalali_date_end_of_month("1403/12/10"));
+        assert_eq!("1402/12/29", crate::jalali_date_end_of_month("1402/12/10"));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_start_end_of_year() {
+        assert_eq!("1403/01/01", crate::jalali_date_start_of_year("1403/05/28"));
+        assert_eq!("1403/12/30", crate::jalali_date_end_of_year("1403/05/28"));
+        assert_eq!("1402/12/29", crate::jalali_date_end_of_year("1402/01/01"));
+    }
+
+    #[pg_test(error = "invalid date 1402/12/30 jalali date")]
+    fn test_jalali_date_end_of_month_invalid() {
+        crate::jalali_date_end_of_month("1402/12/30");
+    }
+
+    #[pg_test]
+    fn test_jalali_weekday_name() {
+        assert_eq!("یکشنبه", crate::jalali_weekday_name("1403/05/28", "fa"));
+        assert_eq!("Sunday", crate::jalali_weekday_name("1403/05/28", "en"));
+        assert_eq!("الأحد", crate::jalali_weekday_name("1403/05/28", "ar"));
+        assert_eq!("الجمعة", crate::jalali_weekday_name("1403/06/02", "ar"));
+    }
+
+    #[pg_test(error = "invalid locale de")]
+    fn test_jalali_weekday_name_invalid_locale() {
+        crate::jalali_weekday_name("1403/05/28", "de");
+    }
+
+    #[pg_test]
+    fn test_jalali_day_number_matches_chrono() {
+        for date in [
+            "0001/01/01",
+            "1348/10/11",
+            "1403/12/30",
+            "1404/01/01",
+            "9999/06/15",
+        ] {
+            let date_value = crate::jalali_date_parse(date);
+            assert_eq!(
+                chrono::Datelike::num_days_from_ce(&crate::jalali_to_naive_date(&date_value))
+                    as i64,
+                crate::jalali_day_number(&date_value)
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_date_diff_extremes() {
+        for year in [-621, 1, 1000000] {
+            let start = format!("{year}/01/01");
+            let end = format!("{}/01/01", year + 1);
+            let length = if crate::jalali_is_leap_year(year) {
+                366
+            } else {
+                365
+            };
+            assert_eq!(length, crate::jalali_date_diff(&start, &end));
+            assert_eq!(
+                -length - 1,
+                crate::jalali_date_diff_with_addition(&end, &start, 1)
+            );
+        }
+        assert_eq!(
+            crate::jalali_date_diff("1000000/01/01", "1000033/01/01"),
+            crate::jalali_date_diff("1400/01/01", "1433/01/01")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_date_diff_regression() {
+        assert_eq!(39, crate::jalali_date_diff("1403/05/28", "1403/07/05"));
+        assert_eq!(-39, crate::jalali_date_diff("1403/07/05", "1403/05/28"));
+        assert_eq!(
+            40,
+            crate::jalali_date_diff_with_addition("1403/05/28", "1403/07/05", 1)
+        );
+        assert_eq!(
+            -40,
+            crate::jalali_date_diff_with_addition("1403/07/05", "1403/05/28", 1)
+        );
+        assert_eq!(0, crate::jalali_date_diff("1403/05/28", "1403/05/28"));
+    }
+
+    #[pg_test]
+    fn test_jalali_month_name_l() {
+        assert_eq!("فروردین", crate::jalali_month_name_l("1403/01/01", "fa"));
+        assert_eq!("اسفند", crate::jalali_month_name_l("1403/12/30", "fa"));
+        assert_eq!("Farvardin", crate::jalali_month_name_l("1403/01/01", "en"));
+        assert_eq!("Esfand", crate::jalali_month_name_l("1403/12/30", "en"));
+        assert_eq!("فروردين", crate::jalali_month_name_l("1403/01/01", "ar"));
+        assert_eq!("إسفند", crate::jalali_month_name_l("1403/12/30", "ar"));
+        assert_eq!("حمل", crate::jalali_month_name_l("1403/01/01", "dari"));
+        assert_eq!("حوت", crate::jalali_month_name_l("1403/12/30", "dari"));
+    }
+
+    #[pg_test(error = "invalid locale ps")]
+    fn test_jalali_month_name_l_invalid_locale() {
+        crate::jalali_month_name_l("1403/01/01", "ps");
+    }
+
+    #[pg_test]
+    fn test_jalali_date_parse_tolerant() {
+        for date in [
+            "1403/05/28",
+            "1403-05-28",
+            "1403/5/28",
+            " 1403-5-28 ",
+            "۱۴۰۳/۰۵/۲۸",
+            "١٤٠٣-٥-٢٨",
+        ] {
+            assert_eq!(
+                "1403/05/28",
+                crate::jalali_date_to_string(&crate::jalali_date_parse(date))
+            );
+        }
+        assert_eq!("1403/05/08", crate::jalali_date_add_days("1403/5/8", 0));
+        assert_eq!("-0001/01/01", crate::jalali_date_add_days("-1-1-1", 0));
+    }
+
+    #[pg_test]
+    fn test_gregorian_date_to_jalali_tolerant() {
+        assert_eq!("1403/01/01", crate::gregorian_date_to_jalali("2024-3-20"));
+        assert_eq!("1403/01/01", crate::gregorian_date_to_jalali("2024/03/20"));
+        assert_eq!("1403/01/01", crate::gregorian_date_to_jalali("۲۰۲۴-۰۳-۲۰"));
+    }
+
+    #[pg_test(error = "invalid date 1403/05-28 format")]
+    fn test_jalali_date_parse_mixed_separators() {
+        crate::jalali_date_parse("1403/05-28");
+    }
+
+    #[pg_test(error = "invalid date 1403/0x/28 month value")]
+    fn test_jalali_date_parse_non_numeric() {
+        crate::jalali_date_parse("1403/0x/28");
+    }
+
+    #[pg_test]
+    fn test_jalali_weekday_count_array() {
+        assert_eq!(
+            vec![1; 7],
+            crate::jalali_weekday_count_array("1403/06/01", "1403/06/07")
+        );
+        assert_eq!(
+            vec![2; 7],
+            crate::jalali_weekday_count_array("1403/06/27", "1403/07/09")
+        );
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 1, 1],
+            crate::jalali_weekday_count_array("1403/06/02", "1403/06/01")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_previous_business_day() {
+        assert_eq!(
+            "1403/06/01",
+            crate::jalali_previous_business_day("1403/06/03", vec![])
+        );
+        assert_eq!(
+            "1403/05/31",
+            crate::jalali_previous_business_day("1403/06/03", vec!["1403/06/01".to_string()])
+        );
+        assert_eq!(
+            "1403/06/04",
+            crate::jalali_previous_business_day("1403/06/05", vec![])
+        );
+        assert_eq!(
+            "1403/12/30",
+            crate::jalali_previous_business_day("1404/01/01", vec![])
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_next_business_day() {
+        assert_eq!(
+            "1403/06/03",
+            crate::jalali_next_business_day("1403/06/01", vec![])
+        );
+        assert_eq!(
+            "1403/06/06",
+            crate::jalali_next_business_day(
+                "1403/06/03",
+                vec!["1403/06/04".to_string(), "1403/06/05".to_string()]
+            )
+        );
+        assert_eq!(
+            "1404/01/05",
+            crate::jalali_next_business_day(
+                "1403/12/29",
+                vec![
+                    "1403/12/30".to_string(),
+                    "1404/01/02".to_string(),
+                    "1404/01/03".to_string(),
+                    "1404/01/04".to_string(),
+                ]
+            )
+        );
+    }
+
+    #[pg_test]
+    fn test_digits_localize() {
+        assert_eq!("۱۴۰۳/۰۵/۲۸", crate::digits_localize("1403/05/28", "fa"));
+        assert_eq!("1403/05/28", crate::digits_localize("1403/05/28", "latin"));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_now_digits() {
+        let latin = crate::jalali_date_now_digits("latin");
+        let persian = crate::jalali_date_now_digits("fa");
+        assert!(!persian.chars().any(|x| x.is_ascii_digit()));
+        assert_eq!(
+            crate::jalali_date_parse(&latin),
+            crate::jalali_date_parse(&persian)
+        );
+    }
+
+    #[pg_test(error = "invalid digits arabic")]
+    fn test_jalali_date_now_digits_invalid() {
+        crate::jalali_date_now_digits("arabic");
+    }
+
+    #[pg_test]
+    fn test_jalali_time_in_window() {
+        assert!(crate::jalali_time_in_window(
+            "1403/05/28 09:15",
+            "08:00",
+            "17:00"
+        ));
+        assert!(crate::jalali_time_in_window(
+            "1403/05/28 08:00:00",
+            "08:00",
+            "17:00"
+        ));
+        assert!(!crate::jalali_time_in_window(
+            "1403/05/28 17:00:00",
+            "08:00",
+            "17:00"
+        ));
+        assert!(!crate::jalali_time_in_window(
+            "1403/05/28 07:59:59",
+            "08:00",
+            "17:00"
+        ));
+    }
+
+    #[pg_test]
+    fn test_jalali_time_in_window_wrapping() {
+        assert!(crate::jalali_time_in_window(
+            "1403/05/28 23:30",
+            "22:00",
+            "06:00"
+        ));
+        assert!(crate::jalali_time_in_window(
+            "1403/05/28 02:00",
+            "22:00",
+            "06:00"
+        ));
+        assert!(!crate::jalali_time_in_window(
+            "1403/05/28 06:00",
+            "22:00",
+            "06:00"
+        ));
+        assert!(!crate::jalali_time_in_window(
+            "1403/05/28 12:00",
+            "22:00",
+            "06:00"
+        ));
+    }
+
+    #[pg_test(error = "invalid time 25:00")]
+    fn test_jalali_time_in_window_invalid() {
+        crate::jalali_time_in_window("1403/05/28 12:00", "25:00", "06:00");
+    }
+
+    #[pg_test]
+    fn test_jalali_end_of_previous_month() {
+        assert_eq!(
+            "1403/06/31",
+            crate::jalali_end_of_previous_month("1403/07/15")
+        );
+        assert_eq!(
+            "1403/12/30",
+            crate::jalali_end_of_previous_month("1404/01/01")
+        );
+        assert_eq!(
+            "1402/12/29",
+            crate::jalali_end_of_previous_month("1403/01/31")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_start_of_next_month() {
+        assert_eq!(
+            "1403/08/01",
+            crate::jalali_start_of_next_month("1403/07/15")
+        );
+        assert_eq!(
+            "1404/01/01",
+            crate::jalali_start_of_next_month("1403/12/01")
+        );
+        assert_eq!(
+            "1403/01/01",
+            crate::jalali_start_of_next_month("1402/12/29")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_quarter_label() {
+        assert_eq!(
+            "فصل اول ۱۴۰۳",
+            crate::jalali_quarter_label("1403/01/01", "fa")
+        );
+        assert_eq!(
+            "فصل اول ۱۴۰۳",
+            crate::jalali_quarter_label("1403/03/31", "fa")
+        );
+        assert_eq!(
+            "فصل دوم ۱۴۰۳",
+            crate::jalali_quarter_label("1403/04/01", "fa")
+        );
+        assert_eq!(
+            "فصل چهارم ۱۴۰۳",
+            crate::jalali_quarter_label("1403/12/30", "fa")
+        );
+        assert_eq!(
+            "فصل چهارم 1403",
+            crate::jalali_quarter_label("1403/10/01", "latin")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_date_diff_months_calendar() {
+        assert_eq!(
+            0,
+            crate::jalali_date_diff_months_calendar("1403/01/01", "1403/01/15", "persian")
+        );
+        assert_eq!(
+            1,
+            crate::jalali_date_diff_months_calendar("1403/01/01", "1403/01/15", "gregorian")
+        );
+        assert_eq!(
+            13,
+            crate::jalali_date_diff_months_calendar("1402/12/29", "1404/01/01", "persian")
+        );
+        assert_eq!(
+            -13,
+            crate::jalali_date_diff_months_calendar("1404/01/01", "1402/12/29", "persian")
+        );
+    }
+
+    #[pg_test(error = "invalid calendar hijri")]
+    fn test_jalali_date_diff_months_calendar_invalid() {
+        crate::jalali_date_diff_months_calendar("1403/01/01", "1403/01/15", "hijri");
+    }
+
+    #[pg_test]
+    fn test_jalali_day_bounds_utc() {
+        let (start, end) = crate::jalali_day_bounds_utc("1403/05/28", "Asia/Tehran");
+        assert_eq!("2024-08-17 20:30:00 UTC", start.to_string());
+        assert_eq!(24, (end - start).num_hours());
+
+        let (start, end) = crate::jalali_day_bounds_utc("1401/01/02", "Asia/Tehran");
+        assert_eq!("2022-03-21 20:30:00 UTC", start.to_string());
+        assert_eq!(23, (end - start).num_hours());
+
+        let (start, end) = crate::jalali_day_bounds_utc("1401/06/30", "Asia/Tehran");
+        assert_eq!(25, (end - start).num_hours());
+    }
+
+    #[pg_test]
+    fn test_jalali_day_range_tz() -> Result<(), spi::Error> {
+        assert_eq!(
+            Some(true),
+            Spi::get_one::<bool>(
+                "SELECT '2024-08-18 12:00:00+03:30'::timestamptz \
+                 <@ jalali_day_range_tz('1403/05/28', 'Asia/Tehran') \
+                 AND NOT '2024-08-19 00:00:00+03:30'::timestamptz \
+                 <@ jalali_day_range_tz('1403/05/28', 'Asia/Tehran')",
+            )?
+        );
+        assert_eq!(
+            Some("23:00:00".to_string()),
+            Spi::get_one::<String>(
+                "SELECT (upper(r) - lower(r))::text \
+                 FROM jalali_day_range_tz('1401/01/02', 'Asia/Tehran') r",
+            )?
+        );
+        Ok(())
+    }
+
+    #[pg_test]
+    fn test_smart_to_jalali() {
+        assert_eq!("1403/05/28", crate::smart_to_jalali("2024-08-18"));
+        assert_eq!("1403/05/28", crate::smart_to_jalali("1403/5/28"));
+        assert_eq!("2024/08/18", crate::smart_to_jalali("2024/08/18"));
+    }
+
+    #[pg_test(error = "invalid date 2024.08.18 ambiguous calendar")]
+    fn test_smart_to_jalali_dotted() {
+        crate::smart_to_jalali("2024.08.18");
+    }
+
+    #[pg_test]
+    fn test_jalali_month_weekday_counts() {
+        assert_eq!(
+            vec![4, 4, 4, 4, 5, 5, 5],
+            crate::jalali_month_weekday_counts(1403, 1)
+        );
+        assert_eq!(
+            crate::jalali_weekday_count_array("1403/07/01", "1403/07/30"),
+            crate::jalali_month_weekday_counts(1403, 7)
+        );
+        assert_eq!(
+            crate::jalali_weekday_count_array("1402/12/01", "1402/12/29"),
+            crate::jalali_month_weekday_counts(1402, 12)
+        );
+    }
+
+    #[pg_test(error = "invalid month 13")]
+    fn test_jalali_month_weekday_counts_invalid() {
+        crate::jalali_month_weekday_counts(1403, 13);
+    }
+
+    #[pg_test]
+    fn test_jalali_epoch_day() {
+        assert_eq!(0, crate::jalali_to_epoch_day("1348/10/11"));
+        assert_eq!(19802, crate::jalali_to_epoch_day("1403/01/01"));
+        assert_eq!("1403/01/01", crate::gregorian_epoch_day_to_jalali(19802));
+        assert_eq!("1348/10/10", crate::gregorian_epoch_day_to_jalali(-1));
+        for date in ["1403/05/28", "1300/01/01", "1500/12/29"] {
+            assert_eq!(
+                date,
+                crate::gregorian_epoch_day_to_jalali(crate::jalali_to_epoch_day(date))
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_gregorian_week() {
+        assert_eq!(33, crate::jalali_gregorian_week("1403/05/28"));
+        assert_eq!(1, crate::jalali_gregorian_week("1403/10/10"));
+        assert_eq!(53, crate::jalali_gregorian_week("1399/10/11"));
+    }
+
+    #[pg_test]
+    fn test_jalali_is_leap_day() {
+        assert!(crate::jalali_is_leap_day("1403/12/30"));
+        assert!(!crate::jalali_is_leap_day("1403/12/29"));
+        assert!(!crate::jalali_is_leap_day("1402/12/29"));
+        assert!(!crate::jalali_is_leap_day("1403/11/30"));
+    }
+
+    #[pg_test(error = "invalid date 1402/12/30 jalali date")]
+    fn test_jalali_is_leap_day_common_year() {
+        crate::jalali_is_leap_day("1402/12/30");
+    }
+
+    #[pg_test]
+    fn test_jalali_add_days_format() {
+        assert_eq!(
+            "30 مرداد 1403",
+            crate::jalali_add_days_format("1403/05/28", 2, "%d %B %Y")
+        );
+        assert_eq!(
+            "01 شهریور 1403",
+            crate::jalali_add_days_format("1403/05/28", 4, "%d %B %Y")
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_sort_array() {
+        assert_eq!(
+            vec!["1402/12/29", "1403/5/8", "1403/05/28", "1403/13/01"],
+            crate::jalali_sort_array(vec![
+                "1403/05/28".to_string(),
+                "1403/13/01".to_string(),
+                "1403/5/8".to_string(),
+                "1402/12/29".to_string(),
+            ])
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_dedupe_array() {
+        assert_eq!(
+            vec!["1403/5/8", "1403/05/28"],
+            crate::jalali_dedupe_array(vec![
+                "1403/5/8".to_string(),
+                "1403/05/28".to_string(),
+                "1403/05/08".to_string(),
+                "not a date".to_string(),
+                "1403-5-08".to_string(),
+            ])
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_quarter_bounds() {
+        assert_eq!(
+            vec![("1403/10/01".to_string(), "1403/12/30".to_string())],
+            crate::jalali_quarter_bounds("1403/11/15").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![("1402/10/01".to_string(), "1402/12/29".to_string())],
+            crate::jalali_quarter_bounds("1402/10/01").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![("1403/04/01".to_string(), "1403/06/31".to_string())],
+            crate::jalali_quarter_bounds("1403/05/28").collect::<Vec<_>>()
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_all_workdays() {
+        assert!(crate::jalali_all_workdays(
+            "1403/06/03",
+            "1403/06/08",
+            vec![]
+        ));
+        assert!(!crate::jalali_all_workdays(
+            "1403/06/01",
+            "1403/06/03",
+            vec![]
+        ));
+        assert!(!crate::jalali_all_workdays(
+            "1403/06/03",
+            "1403/06/08",
+            vec!["1403/06/05".to_string()]
+        ));
+    }
+
+    #[pg_test]
+    fn test_jalali_date_parse_mixed_digits() {
+        for date in ["۱۴۰۳/05/۲۸", "14۰3/٠5/2۸", "١٤٠٣-۰۵-28"] {
+            assert_eq!(
+                "1403/05/28",
+                crate::jalali_date_to_string(&crate::jalali_date_parse(date))
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_any_mixed_digits() {
+        let formats = vec!["%Y/%m/%d".to_string(), "%d-%m-%Y".to_string()];
+        for date in ["۱۴۰۳/05/۲۸", "2۸-٠5-14۰3", " ١٤٠٣/۰۵/28 "] {
+            assert_eq!(
+                Some("1403/05/28".to_string()),
+                crate::jalali_parse_any(date, formats.clone())
+            );
+        }
+        assert_eq!(
+            Some("1403/05/28".to_string()),
+            crate::jalali_parse_any("۲۸ مرداد 14۰3", vec!["%d %B %Y".to_string()])
+        );
+        assert_eq!(None, crate::jalali_parse_any("۳۰-12-14۰2", formats));
+    }
+
+    #[pg_test]
+    fn test_jalali_diff_excluding() {
+        assert_eq!(
+            8,
+            crate::jalali_diff_excluding(
+                "1403/06/01",
+                "1403/06/10",
+                vec![
+                    "1403/06/04".to_string(),
+                    "1403/6/7".to_string(),
+                    "1403/06/04".to_string(),
+                    "1403/07/01".to_string(),
+                ]
+            )
+        );
+        assert_eq!(
+            10,
+            crate::jalali_diff_excluding("1403/06/10", "1403/06/01", vec![])
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_all_calendars() {
+        assert_eq!(
+            vec![(
+                "1403/04/27".to_string(),
+                "2024-07-17".to_string(),
+                "1446/01/10".to_string()
+            )],
+            crate::jalali_all_calendars("1403/4/27").collect::<Vec<_>>()
+        );
+    }
+
+    #[pg_test]
+    fn test_jalali_parse_error() {
+        assert_eq!(None, crate::jalali_parse_error("1403/12/30"));
+        assert_eq!(None, crate::jalali_parse_error("۱۴۰۳-5-8"));
+        for (date, reason) in [
+            ("1403/05", "wrong field count"),
+            ("1403/05/28/01", "wrong field count"),
+            ("14o3/05/28", "non-numeric year"),
+            ("1403/xx/28", "non-numeric month"),
+            ("1403/05/", "non-numeric day"),
+            ("1403/13/01", "month out of range"),
+            ("1403/00/01", "month out of range"),
+            ("1403/300/01", "month out of range"),
+            ("1403/05/256", "day out of range for month"),
+            ("99999999999/01/01", "year out of range"),
+            ("1403/07/31", "day out of range for month"),
+            ("1403/05/00", "day out of range for month"),
+            ("1402/12/30", "invalid leap day"),
+        ] {
+            assert_eq!(Some(reason.to_string()), crate::jalali_parse_error(date));
+        }
     }
 }
 